@@ -0,0 +1,448 @@
+//! Configuration of sync-team itself, as opposed to the desired state of the services, which
+//! comes from the team repo. The configuration is a JSON file passed with `--config`: every
+//! field is optional, and the defaults match the behavior of the tool before it was configurable,
+//! except for the safety limits, which are enforced by default.
+
+use crate::github::{RepoCreationSettings, RepoOptions, RepoPermission, Ruleset};
+use crate::retry::RetryPolicy;
+use crate::safety::SafetyLimits;
+use anyhow::{bail, Context};
+use rust_team_data::v1::Bot;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+
+#[derive(serde::Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Config {
+    /// How requests failing with transient errors are retried.
+    pub(crate) retry: RetryPolicy,
+    /// Limits on the destructive changes applied without `--allow-destructive`.
+    pub(crate) safety: SafetyLimits,
+    /// Configuration specific to the GitHub synchronization.
+    pub(crate) github: GitHubConfig,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct GitHubConfig {
+    /// How many teams or repositories have their current state fetched concurrently.
+    pub(crate) read_concurrency: usize,
+    /// Whether the state of the repositories is fetched in bulk for each org with a few GraphQL
+    /// queries, instead of a few requests for each repository.
+    pub(crate) bulk_repo_reads: bool,
+    /// Policies of the orgs, keyed by org name. The fields configured for the orgs with a built-in
+    /// policy replace the built-in ones.
+    #[serde(deserialize_with = "over_default_orgs")]
+    pub(crate) orgs: HashMap<String, OrgConfig>,
+    /// Orgs whose membership is synchronized with the members of their teams, keyed by org name.
+    /// The membership of the other orgs is left untouched.
+    pub(crate) org_membership: HashMap<String, OrgMembershipConfig>,
+    /// Settings of the GitHub repositories, keyed by `org/name`.
+    pub(crate) repos: HashMap<String, RepoConfig>,
+    /// GitHub Apps whose installations are synchronized. The installations of the other apps are
+    /// left untouched.
+    pub(crate) apps: Vec<GitHubAppConfig>,
+    /// GitHub users of the bots of the team repo, added as collaborators of the repos using them.
+    /// Bots missing from the list, like the ones implemented as apps, don't have a user.
+    pub(crate) bots: Vec<BotConfig>,
+    /// Names of the teams of the bots, which are never deleted even though they're not in the
+    /// team repo.
+    pub(crate) bot_teams: Vec<String>,
+}
+
+/// Deserialize the org policies over the built-in ones, so that configuring an org doesn't drop the
+/// built-in policies of the others, or the built-in fields of its own policy.
+fn over_default_orgs<'de, D>(deserializer: D) -> Result<HashMap<String, OrgConfig>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::Error;
+
+    let configured = HashMap::<String, Map<String, Value>>::deserialize(deserializer)?;
+    let mut orgs = GitHubConfig::default().orgs;
+    for (org, fields) in configured {
+        let mut policy = match orgs.remove(&org) {
+            Some(default) => match serde_json::to_value(default).map_err(D::Error::custom)? {
+                Value::Object(policy) => policy,
+                _ => unreachable!("org policies are serialized as objects"),
+            },
+            None => Map::new(),
+        };
+        policy.extend(fields);
+        let policy = OrgConfig::deserialize(Value::Object(policy))
+            .map_err(|err| D::Error::custom(format!("invalid policy of org {org}: {err}")))?;
+        orgs.insert(org, policy);
+    }
+    Ok(orgs)
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct BotConfig {
+    pub(crate) bot: Bot,
+    /// GitHub name of the bot user.
+    pub(crate) user: String,
+    /// Permission of the bot on the repos using it.
+    #[serde(default = "default_bot_permission")]
+    pub(crate) permission: RepoPermission,
+}
+
+fn default_bot_permission() -> RepoPermission {
+    RepoPermission::Write
+}
+
+impl BotConfig {
+    fn new(bot: Bot, user: &str) -> Self {
+        Self {
+            bot,
+            user: user.to_string(),
+            permission: default_bot_permission(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct GitHubAppConfig {
+    /// Name of the app, used in the plan and in the `apps` of the repos.
+    pub(crate) name: String,
+    /// ID of the app itself, not of its installations.
+    pub(crate) app_id: u64,
+    /// Bot of the team repo enabling the app on the repos using it.
+    #[serde(default)]
+    pub(crate) bot: Option<Bot>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct OrgConfig {
+    /// Whether the teams of the org that are not in the team repo are deleted.
+    pub(crate) delete_unmanaged_teams: bool,
+    /// Teams that are never deleted, and whose permissions on repos are never removed unless they
+    /// have a policy in `team_permissions`.
+    pub(crate) ignored_teams: Vec<String>,
+    /// Permission policies of teams, keyed by team name.
+    pub(crate) team_permissions: BTreeMap<String, TeamPermissionPolicy>,
+    /// GitHub names of the users whose permissions on repos are never removed.
+    pub(crate) ignored_collaborators: Vec<String>,
+    /// Whether the repos of the org that are not in the team repo are listed in the plan.
+    pub(crate) report_unmanaged_repos: bool,
+    /// Names of the unmanaged repos that are known, as opposed to new ones.
+    pub(crate) known_unmanaged_repos: Vec<String>,
+    /// Whether the run fails when new unmanaged repos are found, even if the plan is only printed
+    /// or saved.
+    pub(crate) fail_on_new_unmanaged_repos: bool,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct TeamPermissionPolicy {
+    /// Permission the team keeps on the repos where the team repo doesn't give it access, for
+    /// example because GitHub grants it. Higher permissions are downgraded to it, instead of
+    /// being removed.
+    pub(crate) floor: Option<RepoPermission>,
+    /// Highest permission the team repo can give the team on a repo.
+    pub(crate) ceiling: Option<RepoPermission>,
+}
+
+#[derive(serde::Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct OrgMembershipConfig {
+    /// Whether the org members not in any team of the org are removed from it. Otherwise they're
+    /// only reported in the plan.
+    pub(crate) remove_unmanaged: bool,
+    /// GitHub names of users allowed to be members of the org without being in any of its teams,
+    /// for example bots and outside collaborators who were given membership.
+    pub(crate) allowed_members: Vec<String>,
+}
+
+#[derive(serde::Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct RepoConfig {
+    /// Merge methods and features of the repo. Its visibility comes from the team repo.
+    pub(crate) options: RepoOptionsConfig,
+    /// Settings only applied when the repo is created.
+    pub(crate) creation: RepoCreationSettings,
+    /// Topics of the repo. The topics of repos without this setting are left untouched.
+    ///
+    /// They're configured here rather than in the team repo, as the Team API doesn't expose them.
+    pub(crate) topics: Option<Vec<String>>,
+    /// Rulesets of the repo. Rulesets missing from the list are deleted, while the rulesets of
+    /// repos without this setting are left untouched. The rulesets of the org aren't managed.
+    pub(crate) rulesets: Option<Vec<Ruleset>>,
+    /// Whether the rulesets replace the branch protections of the team repo, which are then
+    /// deleted from GitHub once the rulesets are in place.
+    pub(crate) replace_branch_protections: bool,
+    /// Names of the apps of `github.apps` installed on the repo, in addition to the ones enabled
+    /// by its bots.
+    pub(crate) apps: Vec<String>,
+    /// Settings of the branch protections of the team repo that it doesn't define, keyed by
+    /// pattern.
+    pub(crate) branch_protections: HashMap<String, BranchProtectionConfig>,
+}
+
+/// The options of a repo that are synchronized when they're set. They're converted to the
+/// `RepoOptions` read from GitHub, which has to accept the unknown fields of the API responses,
+/// without a visibility as it comes from the team repo.
+#[derive(serde::Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct RepoOptionsConfig {
+    pub(crate) allow_merge_commit: Option<bool>,
+    pub(crate) allow_squash_merge: Option<bool>,
+    pub(crate) allow_rebase_merge: Option<bool>,
+    pub(crate) delete_branch_on_merge: Option<bool>,
+    pub(crate) has_issues: Option<bool>,
+    pub(crate) has_wiki: Option<bool>,
+    pub(crate) has_projects: Option<bool>,
+    pub(crate) has_discussions: Option<bool>,
+}
+
+impl From<&RepoOptionsConfig> for RepoOptions {
+    fn from(config: &RepoOptionsConfig) -> Self {
+        RepoOptions {
+            allow_merge_commit: config.allow_merge_commit,
+            allow_squash_merge: config.allow_squash_merge,
+            allow_rebase_merge: config.allow_rebase_merge,
+            delete_branch_on_merge: config.delete_branch_on_merge,
+            has_issues: config.has_issues,
+            has_wiki: config.has_wiki,
+            has_projects: config.has_projects,
+            has_discussions: config.has_discussions,
+            visibility: None,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct BranchProtectionConfig {
+    /// Whether the protection also applies to admins.
+    pub(crate) is_admin_enforced: bool,
+    /// ID of the app that must report the CI checks of the team repo, for example 15368 for
+    /// GitHub Actions. Any app can report them otherwise.
+    pub(crate) status_checks_app_id: Option<u64>,
+    /// Whether branches must be up to date with the base branch before merging. This and the
+    /// following settings keep their current value when they're not configured, and are disabled
+    /// on new branch protections.
+    pub(crate) requires_strict_status_checks: Option<bool>,
+    pub(crate) requires_code_owner_reviews: Option<bool>,
+    pub(crate) requires_linear_history: Option<bool>,
+    pub(crate) requires_conversation_resolution: Option<bool>,
+    pub(crate) allows_force_pushes: Option<bool>,
+    pub(crate) allows_deletions: Option<bool>,
+    /// Teams of the org of the repo allowed to dismiss reviews. When no team or user is allowed,
+    /// anyone with write access can dismiss reviews. The current allowances are kept when
+    /// neither the teams nor the users are configured.
+    pub(crate) review_dismissal_teams: Option<Vec<String>>,
+    /// GitHub names of the users allowed to dismiss reviews.
+    pub(crate) review_dismissal_users: Option<Vec<String>>,
+}
+
+impl Default for BranchProtectionConfig {
+    fn default() -> Self {
+        Self {
+            is_admin_enforced: true,
+            status_checks_app_id: None,
+            requires_strict_status_checks: None,
+            requires_code_owner_reviews: None,
+            requires_linear_history: None,
+            requires_conversation_resolution: None,
+            allows_force_pushes: None,
+            allows_deletions: None,
+            review_dismissal_teams: None,
+            review_dismissal_users: None,
+        }
+    }
+}
+
+impl Default for GitHubConfig {
+    fn default() -> Self {
+        Self {
+            read_concurrency: 4,
+            bulk_repo_reads: true,
+            orgs: HashMap::from([
+                (
+                    "rust-lang".to_string(),
+                    OrgConfig {
+                        delete_unmanaged_teams: true,
+                        ignored_teams: vec!["security".to_string()],
+                        // GitHub gives security read access to all the repos of the org, through
+                        // the security manager role.
+                        team_permissions: BTreeMap::from([(
+                            "security".to_string(),
+                            TeamPermissionPolicy {
+                                floor: Some(RepoPermission::Read),
+                                ceiling: None,
+                            },
+                        )]),
+                        ..OrgConfig::default()
+                    },
+                ),
+                (
+                    "rust-lang-nursery".to_string(),
+                    OrgConfig {
+                        delete_unmanaged_teams: true,
+                        ..OrgConfig::default()
+                    },
+                ),
+            ]),
+            org_membership: HashMap::new(),
+            repos: HashMap::new(),
+            apps: vec![GitHubAppConfig {
+                name: "RenovateBot".to_string(),
+                app_id: 2740,
+                bot: Some(Bot::Renovate),
+            }],
+            bots: vec![
+                BotConfig::new(Bot::Bors, "bors"),
+                BotConfig::new(Bot::Highfive, "rust-highfive"),
+                BotConfig::new(Bot::RustTimer, "rust-timer"),
+                BotConfig::new(Bot::Rustbot, "rustbot"),
+                BotConfig::new(Bot::Rfcbot, "rfcbot"),
+            ],
+            bot_teams: ["bors", "highfive", "rfcbot", "bots"]
+                .iter()
+                .map(|team| team.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl Config {
+    pub(crate) fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read the configuration at {}", path.display()))?;
+        Self::parse(&content)
+            .with_context(|| format!("failed to parse the configuration at {}", path.display()))
+    }
+
+    fn parse(content: &str) -> anyhow::Result<Self> {
+        let config: Config = serde_json::from_str(content)?;
+        config.github.validate()?;
+        Ok(config)
+    }
+}
+
+impl GitHubConfig {
+    /// Check the consistency of the settings that can't be expressed in their types.
+    fn validate(&self) -> anyhow::Result<()> {
+        let mut names = HashSet::new();
+        let mut app_ids = HashSet::new();
+        for app in &self.apps {
+            if !names.insert(&app.name) {
+                bail!(
+                    "app {} is configured multiple times in github.apps",
+                    app.name
+                );
+            }
+            if !app_ids.insert(app.app_id) {
+                bail!(
+                    "app ID {} is used by multiple apps in github.apps",
+                    app.app_id
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_with_built_in_org_policies() {
+        let config = Config::parse(
+            r#"{"github": {
+                "orgs": {
+                    "rust-lang": {"report_unmanaged_repos": true},
+                    "rust-embedded": {"delete_unmanaged_teams": true}
+                }
+            }}"#,
+        )
+        .unwrap()
+        .github;
+
+        let rust_lang = &config.orgs["rust-lang"];
+        assert!(rust_lang.report_unmanaged_repos);
+        assert!(rust_lang.delete_unmanaged_teams);
+        assert_eq!(rust_lang.ignored_teams, ["security"]);
+        assert!(config.orgs["rust-lang-nursery"].delete_unmanaged_teams);
+        assert!(config.orgs["rust-embedded"].delete_unmanaged_teams);
+    }
+
+    #[test]
+    fn replace_built_in_bots() {
+        let config = Config::parse(
+            r#"{"github": {
+                "apps": [{"name": "triagebot", "app_id": 7}],
+                "bots": [
+                    {"bot": "bors", "user": "bors-ng"},
+                    {"bot": "rustbot", "user": "rustbot"}
+                ],
+                "bot_teams": ["bors", "triagebot"]
+            }}"#,
+        )
+        .unwrap()
+        .github;
+
+        let apps = config.apps.iter().map(|a| &a.name).collect::<Vec<_>>();
+        assert_eq!(apps, ["triagebot"]);
+        // The built-in users of the bots missing from the list, like rfcbot, are dropped.
+        let bots = config.bots.iter().map(|b| &b.user).collect::<Vec<_>>();
+        assert_eq!(bots, ["bors-ng", "rustbot"]);
+        assert_eq!(config.bot_teams, ["bors", "triagebot"]);
+
+        let config = Config::parse("{}").unwrap().github;
+        assert_eq!(config.apps.len(), 1);
+        assert_eq!(config.bots.len(), 5);
+        assert_eq!(config.bot_teams.len(), 4);
+    }
+
+    #[test]
+    fn reject_unknown_org_policy_fields() {
+        let err = Config::parse(r#"{"github": {"orgs": {"rust-lang": {"delete_teams": true}}}}"#)
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("invalid policy of org rust-lang: unknown field `delete_teams`"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn reject_unknown_repo_options() {
+        let err = Config::parse(
+            r#"{"github": {"repos": {"rust-lang/rust": {"options": {"has_wikis": false}}}}}"#,
+        )
+        .unwrap_err();
+        assert!(
+            format!("{err:#}").contains("unknown field `has_wikis`"),
+            "{err:#}"
+        );
+    }
+
+    #[test]
+    fn reject_duplicate_apps() {
+        let err = |config: &str| Config::parse(config).unwrap_err().to_string();
+        assert_eq!(
+            err(r#"{"github": {"apps": [
+                {"name": "RenovateBot", "app_id": 2740},
+                {"name": "RenovateBot", "app_id": 2741}
+            ]}}"#),
+            "app RenovateBot is configured multiple times in github.apps"
+        );
+        assert_eq!(
+            err(r#"{"github": {"apps": [
+                {"name": "RenovateBot", "app_id": 2740},
+                {"name": "Renovate", "app_id": 2740}
+            ]}}"#),
+            "app ID 2740 is used by multiple apps in github.apps"
+        );
+        assert!(
+            Config::parse(r#"{"github": {"apps": [{"name": "triagebot", "app_id": 7}]}}"#).is_ok()
+        );
+    }
+}
@@ -0,0 +1,94 @@
+use log::error;
+use std::fmt::Write;
+
+/// Failures that happened while applying independent changes.
+///
+/// By default the first failure aborts the run. In `--keep-going` mode failures are recorded
+/// instead, so that the changes that don't depend on the failed one are still applied, and all
+/// the failures are reported at the end of the run.
+pub(crate) struct Failures {
+    keep_going: bool,
+    failures: Vec<(String, anyhow::Error)>,
+}
+
+impl Failures {
+    pub(crate) fn new(keep_going: bool) -> Self {
+        Self {
+            keep_going,
+            failures: Vec::new(),
+        }
+    }
+
+    /// Handle the outcome of an independent change, described by `context` (for example
+    /// "removing alice from team rust-lang/infra"). Outside of `--keep-going` mode the failure is
+    /// returned to abort the run, otherwise it's recorded and `None` is returned, so that the
+    /// changes depending on this one can be skipped.
+    pub(crate) fn handle<T, F>(
+        &mut self,
+        result: anyhow::Result<T>,
+        context: F,
+    ) -> anyhow::Result<Option<T>>
+    where
+        F: FnOnce() -> String,
+    {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(err) if self.keep_going => {
+                let context = context();
+                error!("error while {context}: {err:?}");
+                self.failures.push((context, err));
+                Ok(None)
+            }
+            Err(err) => Err(err.context(format!("error while {}", context()))),
+        }
+    }
+
+    /// Fail with a summary of all the recorded failures, if any.
+    pub(crate) fn into_result(self) -> anyhow::Result<()> {
+        if self.failures.is_empty() {
+            return Ok(());
+        }
+        let mut summary = format!("{} change(s) failed to apply:", self.failures.len());
+        for (context, err) in &self.failures {
+            write!(summary, "\n  - {context}: {err:#}").unwrap();
+        }
+        Err(anyhow::Error::msg(summary))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn abort_on_first_failure() {
+        let mut failures = Failures::new(false);
+        let err = failures
+            .handle(Err::<(), _>(anyhow::anyhow!("404")), || {
+                "deleting team a".into()
+            })
+            .unwrap_err();
+        assert_eq!(format!("{err:#}"), "error while deleting team a: 404");
+        assert!(failures.into_result().is_ok());
+    }
+
+    #[test]
+    fn keep_going_and_report_all_failures() {
+        let mut failures = Failures::new(true);
+        let result = failures.handle(Err::<(), _>(anyhow::anyhow!("404")), || {
+            "deleting team a".into()
+        });
+        assert!(result.unwrap().is_none());
+        let result = failures.handle(Ok(()), || "deleting team b".into());
+        assert!(result.unwrap().is_some());
+        let result = failures.handle(Err::<(), _>(anyhow::anyhow!("500")), || {
+            "deleting team c".into()
+        });
+        assert!(result.unwrap().is_none());
+        insta::assert_snapshot!(failures.into_result().unwrap_err().to_string(), @r###"
+        2 change(s) failed to apply:
+          - deleting team a: 404
+          - deleting team c: 500
+        "###);
+    }
+}
@@ -1,10 +1,14 @@
+mod rate_limit;
 mod read;
+#[cfg(test)]
+mod tests;
 mod write;
 
+use crate::retry::RetryPolicy;
 use crate::utils::ResponseExt;
 use anyhow::{bail, Context};
 use hyper_old_types::header::{Link, RelationType};
-use log::{debug, trace};
+use log::{debug, trace, warn};
 use reqwest::header::HeaderMap;
 use reqwest::{
     blocking::{Client, RequestBuilder, Response},
@@ -13,7 +17,9 @@ use reqwest::{
 };
 use serde::{de::DeserializeOwned, Deserialize};
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fmt;
+use std::sync::Arc;
 
 pub(crate) use read::{GitHubApiRead, GithubRead};
 pub(crate) use write::GitHubWrite;
@@ -22,10 +28,16 @@ pub(crate) use write::GitHubWrite;
 pub(crate) struct HttpClient {
     client: Client,
     base_url: String,
+    retry: RetryPolicy,
+    rate_limits: Arc<rate_limit::RateLimits>,
 }
 
 impl HttpClient {
-    pub(crate) fn from_url_and_token(mut base_url: String, token: String) -> anyhow::Result<Self> {
+    pub(crate) fn from_url_and_token(
+        mut base_url: String,
+        token: String,
+        retry: RetryPolicy,
+    ) -> anyhow::Result<Self> {
         let mut builder = reqwest::blocking::ClientBuilder::default();
         let mut map = HeaderMap::default();
         let mut auth = HeaderValue::from_str(&format!("token {}", token))?;
@@ -45,11 +57,13 @@ impl HttpClient {
         Ok(Self {
             client: builder.build()?,
             base_url,
+            retry,
+            rate_limits: Arc::default(),
         })
     }
 
     fn req(&self, method: Method, url: &str) -> anyhow::Result<RequestBuilder> {
-        let url = if url.starts_with("https://") {
+        let url = if url.starts_with("https://") || url.starts_with("http://") {
             Cow::Borrowed(url)
         } else {
             Cow::Owned(format!("{}{url}", self.base_url))
@@ -58,13 +72,61 @@ impl HttpClient {
         Ok(self.client.request(method, url.as_ref()))
     }
 
+    /// Send a request, waiting for the rate limit budget to be available first. Requests failing
+    /// with transient errors are retried according to the retry policy, and requests rejected by
+    /// a rate limit after the delay requested by GitHub, as long as they are `idempotent`: the
+    /// response is returned whatever its status otherwise.
+    fn execute(
+        &self,
+        method: &Method,
+        url: &str,
+        request: &RequestBuilder,
+        idempotent: bool,
+    ) -> anyhow::Result<Response> {
+        let resource = if url == "graphql" { "graphql" } else { "core" };
+        let mut attempt = 0;
+        loop {
+            self.rate_limits.wait_for_budget(resource);
+            let resp = self.retry.send(request, idempotent)?;
+            self.rate_limits.update(resp.headers());
+
+            let status = resp.status();
+            if status != StatusCode::FORBIDDEN && status != StatusCode::TOO_MANY_REQUESTS {
+                return Ok(resp);
+            }
+            // The body is needed to tell secondary rate limits apart from permission errors.
+            let headers = resp.headers().clone();
+            let body = resp.text()?;
+            match rate_limit::retry_delay(status, &headers, &body, attempt) {
+                Some(delay) if idempotent && attempt < rate_limit::MAX_RETRIES => {
+                    warn!(
+                        "{method} request to '{url}' hit a GitHub rate limit, retrying in {}s",
+                        delay.as_secs()
+                    );
+                    std::thread::sleep(delay);
+                    attempt += 1;
+                }
+                _ => {
+                    bail!("{method} request to '{url}' failed with status {status}. Body: {body:?}")
+                }
+            }
+        }
+    }
+
+    /// Send a request without a body, returning the response whatever its status.
+    fn send_empty(&self, method: Method, url: &str) -> anyhow::Result<Response> {
+        let request = self.req(method.clone(), url)?;
+        self.execute(&method, url, &request, method.is_idempotent())
+    }
+
     fn send<T: serde::Serialize + std::fmt::Debug>(
         &self,
         method: Method,
         url: &str,
         body: &T,
     ) -> Result<Response, anyhow::Error> {
-        let resp = self.req(method, url)?.json(body).send()?;
+        let request = self.req(method.clone(), url)?.json(body);
+        let resp = self.execute(&method, url, &request, method.is_idempotent())?;
         resp.custom_error_for_status()
     }
 
@@ -73,7 +135,7 @@ impl HttpClient {
         method: Method,
         url: &str,
     ) -> Result<Option<T>, anyhow::Error> {
-        let resp = self.req(method.clone(), url)?.send()?;
+        let resp = self.send_empty(method.clone(), url)?;
         match resp.status() {
             StatusCode::OK => Ok(Some(resp.json_annotated().with_context(|| {
                 format!("Failed to decode response body on {method} request to '{url}'")
@@ -93,21 +155,43 @@ impl HttpClient {
             query: &'a str,
             variables: V,
         }
-        let resp = self
+        let request = self
             .req(Method::POST, "graphql")?
-            .json(&Request { query, variables })
-            .send()?
-            .custom_error_for_status()?;
-
-        let res: GraphResult<R> = resp.json_annotated().with_context(|| {
-            format!("Failed to decode response body on graphql request with query '{query}'")
-        })?;
-        if let Some(error) = res.errors.first() {
-            bail!("graphql error: {}", error.message);
-        } else if let Some(data) = res.data {
-            Ok(data)
-        } else {
-            bail!("missing graphql data");
+            .json(&Request { query, variables });
+        let idempotent = !query.trim_start().starts_with("mutation");
+        let mut attempt = 0;
+        loop {
+            let resp = self
+                .execute(&Method::POST, "graphql", &request, idempotent)?
+                .custom_error_for_status()?;
+            let headers = resp.headers().clone();
+
+            let res: GraphResult<R> = resp.json_annotated().with_context(|| {
+                format!("Failed to decode response body on graphql request with query '{query}'")
+            })?;
+            if let Some(error) = res.errors.first() {
+                // GitHub reports the exhaustion of the GraphQL rate limit as a query error: the
+                // headers of the response tell when the budget resets, otherwise we back off like
+                // for secondary rate limits.
+                if error.kind.as_deref() == Some("RATE_LIMITED")
+                    && idempotent
+                    && attempt < rate_limit::MAX_RETRIES
+                {
+                    let delay = rate_limit::graphql_retry_delay(&headers, attempt);
+                    warn!(
+                        "graphql request hit the GitHub rate limit, retrying in {}s",
+                        delay.as_secs()
+                    );
+                    std::thread::sleep(delay);
+                    attempt += 1;
+                    continue;
+                }
+                bail!("graphql error: {}", error.message);
+            } else if let Some(data) = res.data {
+                return Ok(data);
+            } else {
+                bail!("missing graphql data");
+            }
         }
     }
 
@@ -119,8 +203,7 @@ impl HttpClient {
         let mut next = Some(url);
         while let Some(next_url) = next.take() {
             let resp = self
-                .req(method.clone(), &next_url)?
-                .send()?
+                .send_empty(method.clone(), &next_url)?
                 .custom_error_for_status()?;
 
             // Extract the next page
@@ -167,6 +250,8 @@ struct GraphResult<T> {
 
 #[derive(Debug, serde::Deserialize)]
 struct GraphError {
+    #[serde(rename = "type")]
+    kind: Option<String>,
     message: String,
 }
 
@@ -207,15 +292,23 @@ pub(crate) struct Team {
     /// The slug usually matches the name but can differ.
     /// For example, a team named rustup.rs would have a slug rustup-rs.
     pub(crate) slug: String,
+    /// The team this team is nested in, if any.
+    #[serde(default)]
+    pub(crate) parent: Option<ParentTeam>,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, Debug, Clone)]
+pub(crate) struct ParentTeam {
+    pub(crate) name: String,
+}
+
+#[derive(serde::Deserialize, Debug, Clone, PartialEq)]
 pub(crate) struct RepoTeam {
     pub(crate) name: String,
     pub(crate) permission: RepoPermission,
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, Debug, Clone, PartialEq)]
 pub(crate) struct RepoUser {
     #[serde(alias = "login")]
     pub(crate) name: String,
@@ -223,17 +316,32 @@ pub(crate) struct RepoUser {
     pub(crate) permission: RepoPermission,
 }
 
-#[derive(Copy, Clone, serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+/// Permissions are ordered by their position in the hierarchy of permissions, each one including
+/// the permissions below it.
+#[derive(
+    Copy, Clone, serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord,
+)]
 #[serde(rename_all = "snake_case")]
 pub(crate) enum RepoPermission {
+    // The API also uses the older term 'pull' for 'read'
+    #[serde(rename(serialize = "pull"), alias = "pull")]
+    Read,
+    Triage,
     // While the GitHub UI uses the term 'write', the API still uses the older term 'push'
     #[serde(rename(serialize = "push"), alias = "push")]
     Write,
-    Admin,
     Maintain,
-    Triage,
-    #[serde(alias = "pull")]
-    Read,
+    Admin,
+}
+
+/// Deserialize a permission returned by the GraphQL API, which uses uppercase names.
+fn graphql_permission<'de, D>(deserializer: D) -> Result<RepoPermission, D::Error>
+where
+    D: serde::de::Deserializer<'de>,
+{
+    use serde::de::IntoDeserializer;
+    let permission = String::deserialize(deserializer)?.to_lowercase();
+    RepoPermission::deserialize(IntoDeserializer::<D::Error>::into_deserializer(permission))
 }
 
 impl fmt::Display for RepoPermission {
@@ -260,7 +368,7 @@ pub(crate) struct RepoAppInstallation {
     pub(crate) name: String,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, Debug, Clone, PartialEq)]
 pub(crate) struct Repo {
     pub(crate) node_id: String,
     #[serde(rename = "id")]
@@ -269,10 +377,25 @@ pub(crate) struct Repo {
     #[serde(alias = "owner", deserialize_with = "repo_owner")]
     pub(crate) org: String,
     pub(crate) description: Option<String>,
+    /// `None` when empty, as the REST API returns an empty homepage after it's removed.
+    #[serde(default, deserialize_with = "non_empty")]
     pub(crate) homepage: Option<String>,
     pub(crate) archived: bool,
     #[serde(default)]
     pub(crate) allow_auto_merge: Option<bool>,
+    #[serde(flatten)]
+    pub(crate) options: RepoOptions,
+}
+
+/// The current state of a repo, as needed to compute its diff.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RepoState {
+    pub(crate) repo: Repo,
+    pub(crate) teams: Vec<RepoTeam>,
+    pub(crate) collaborators: Vec<RepoUser>,
+    /// The branch protections by pattern, along with their IDs.
+    pub(crate) branch_protections: HashMap<String, (String, BranchProtection)>,
+    pub(crate) topics: Vec<String>,
 }
 
 fn repo_owner<'de, D>(deserializer: D) -> Result<String, D::Error>
@@ -284,7 +407,7 @@ where
 }
 
 /// An object with a `login` field
-#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[derive(serde::Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub(crate) struct Login {
     pub(crate) login: String,
 }
@@ -326,19 +449,49 @@ fn team_node_id(id: u64) -> String {
     base64::encode(format!("04:Team{id}"))
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize)]
-#[serde(rename_all = "camelCase")]
+fn app_node_id(id: u64) -> String {
+    base64::encode(format!("03:App{id}"))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all(deserialize = "camelCase"))]
 pub(crate) struct BranchProtection {
     pub(crate) pattern: String,
     pub(crate) is_admin_enforced: bool,
     pub(crate) dismisses_stale_reviews: bool,
     #[serde(default, deserialize_with = "nullable")]
     pub(crate) required_approving_review_count: u8,
-    #[serde(default, deserialize_with = "nullable")]
-    pub(crate) required_status_check_contexts: Vec<String>,
-    #[serde(deserialize_with = "allowances")]
+    #[serde(default, deserialize_with = "status_checks")]
+    pub(crate) required_status_checks: Vec<RequiredStatusCheck>,
+    /// Read separately from the other fields, as they're paginated.
+    #[serde(skip_deserializing)]
     pub(crate) push_allowances: Vec<PushAllowanceActor>,
     pub(crate) requires_approving_reviews: bool,
+    #[serde(default)]
+    pub(crate) requires_strict_status_checks: bool,
+    #[serde(default)]
+    pub(crate) requires_code_owner_reviews: bool,
+    #[serde(default)]
+    pub(crate) requires_linear_history: bool,
+    #[serde(default)]
+    pub(crate) requires_conversation_resolution: bool,
+    #[serde(default)]
+    pub(crate) allows_force_pushes: bool,
+    #[serde(default)]
+    pub(crate) allows_deletions: bool,
+    /// Actors allowed to dismiss reviews. Anyone with write access can if it's empty.
+    ///
+    /// Read separately from the other fields, as they're paginated.
+    #[serde(skip_deserializing)]
+    pub(crate) review_dismissal_allowances: Vec<PushAllowanceActor>,
+}
+
+fn non_empty<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: serde::de::Deserializer<'de>,
+{
+    let opt = Option::<String>::deserialize(deserializer)?;
+    Ok(opt.filter(|s| !s.is_empty()))
 }
 
 fn nullable<'de, D, T>(deserializer: D) -> Result<T, D::Error>
@@ -350,24 +503,42 @@ where
     Ok(opt.unwrap_or_default())
 }
 
-fn allowances<'de, D>(deserializer: D) -> Result<Vec<PushAllowanceActor>, D::Error>
+/// A status check required to pass before merging into a protected branch
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub(crate) struct RequiredStatusCheck {
+    pub(crate) context: String,
+    /// ID of the app that must report the check. Any app can report it otherwise, as long as the
+    /// check has the right name.
+    pub(crate) app_id: Option<u64>,
+}
+
+fn status_checks<'de, D>(deserializer: D) -> Result<Vec<RequiredStatusCheck>, D::Error>
 where
     D: serde::de::Deserializer<'de>,
 {
     #[derive(Deserialize)]
-    struct Allowances {
-        nodes: Vec<Actor>,
+    struct Check {
+        context: String,
+        app: Option<App>,
     }
     #[derive(Deserialize)]
-    struct Actor {
-        actor: PushAllowanceActor,
+    #[serde(rename_all = "camelCase")]
+    struct App {
+        database_id: u64,
     }
-    let allowances = Allowances::deserialize(deserializer)?;
-    Ok(allowances.nodes.into_iter().map(|a| a.actor).collect())
+    let checks: Option<Vec<Check>> = Option::deserialize(deserializer)?;
+    Ok(checks
+        .unwrap_or_default()
+        .into_iter()
+        .map(|check| RequiredStatusCheck {
+            context: check.context,
+            app_id: check.app.map(|app| app.database_id),
+        })
+        .collect())
 }
 
-/// Entities that can be allowed to push to a branch in a repo
-#[derive(Clone, Deserialize, Debug, PartialEq, Eq)]
+/// Entities that can be allowed to push to a branch in a repo, or to dismiss its reviews
+#[derive(Clone, serde::Serialize, Deserialize, Debug, PartialEq, Eq)]
 #[serde(untagged)]
 pub(crate) enum PushAllowanceActor {
     User(UserPushAllowanceActor),
@@ -375,13 +546,13 @@ pub(crate) enum PushAllowanceActor {
 }
 
 /// User who can be allowed to push to a branch in a repo
-#[derive(Clone, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Clone, serde::Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub(crate) struct UserPushAllowanceActor {
     pub(crate) login: String,
 }
 
 /// Team that can be allowed to push to a branch in a repo
-#[derive(Clone, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Clone, serde::Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub(crate) struct TeamPushAllowanceActor {
     pub(crate) organization: Login,
     pub(crate) name: String,
@@ -392,10 +563,360 @@ pub(crate) enum BranchProtectionOp {
     UpdateBranchProtection(String),
 }
 
-#[derive(PartialEq)]
+/// A repository ruleset, the successor of branch protection rules. Unlike branch protections,
+/// rulesets can also protect tags, let specific actors bypass them and require a merge queue.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub(crate) struct Ruleset {
+    pub(crate) name: String,
+    #[serde(default)]
+    pub(crate) target: RulesetTarget,
+    #[serde(default)]
+    pub(crate) enforcement: RulesetEnforcement,
+    #[serde(default)]
+    pub(crate) bypass_actors: Vec<RulesetBypassActor>,
+    #[serde(default)]
+    pub(crate) conditions: RulesetConditions,
+    #[serde(default)]
+    pub(crate) rules: Vec<RulesetRule>,
+}
+
+impl Ruleset {
+    /// Sort the parts of the ruleset GitHub doesn't return in a stable order, so that rulesets
+    /// can be compared.
+    pub(crate) fn normalized(mut self) -> Self {
+        self.bypass_actors
+            .sort_by_key(|actor| (actor.actor_type, actor.actor_id));
+        self.conditions.ref_name.include.sort();
+        self.conditions.ref_name.exclude.sort();
+        self.rules.sort_by_key(|rule| rule.kind());
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RulesetTarget {
+    #[default]
+    Branch,
+    Tag,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RulesetEnforcement {
+    #[default]
+    Active,
+    /// Only report the violations of the ruleset, available on GitHub Enterprise.
+    Evaluate,
+    Disabled,
+}
+
+/// An actor allowed to bypass a ruleset
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub(crate) struct RulesetBypassActor {
+    /// ID of the team, app or repository role, `1` for org admins and unset for deploy keys.
+    #[serde(default)]
+    pub(crate) actor_id: Option<u64>,
+    pub(crate) actor_type: RulesetActorType,
+    #[serde(default)]
+    pub(crate) bypass_mode: RulesetBypassMode,
+}
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+pub(crate) enum RulesetActorType {
+    Integration,
+    OrganizationAdmin,
+    RepositoryRole,
+    Team,
+    DeployKey,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RulesetBypassMode {
+    #[default]
+    Always,
+    /// The actor can only bypass the ruleset when merging pull requests.
+    PullRequest,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub(crate) struct RulesetConditions {
+    #[serde(default)]
+    pub(crate) ref_name: RulesetRefNameCondition,
+}
+
+/// Refs the ruleset applies to, as `fnmatch` patterns of full ref names (`refs/heads/main`), or
+/// the special `~DEFAULT_BRANCH` and `~ALL` values.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub(crate) struct RulesetRefNameCondition {
+    #[serde(default)]
+    pub(crate) include: Vec<String>,
+    #[serde(default)]
+    pub(crate) exclude: Vec<String>,
+}
+
+/// A rule of a ruleset. The parameters of the rules can be omitted to use their defaults.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "type", content = "parameters", rename_all = "snake_case")]
+pub(crate) enum RulesetRule {
+    /// Only allow the bypass actors to create matching refs
+    Creation,
+    /// Only allow the bypass actors to update matching refs
+    Update(UpdateRuleParameters),
+    /// Only allow the bypass actors to delete matching refs
+    Deletion,
+    RequiredLinearHistory,
+    RequiredSignatures,
+    /// Prevent force pushes
+    NonFastForward,
+    PullRequest(PullRequestRuleParameters),
+    RequiredStatusChecks(RequiredStatusChecksRuleParameters),
+    MergeQueue(MergeQueueRuleParameters),
+}
+
+impl RulesetRule {
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            RulesetRule::Creation => "creation",
+            RulesetRule::Update(_) => "update",
+            RulesetRule::Deletion => "deletion",
+            RulesetRule::RequiredLinearHistory => "required_linear_history",
+            RulesetRule::RequiredSignatures => "required_signatures",
+            RulesetRule::NonFastForward => "non_fast_forward",
+            RulesetRule::PullRequest(_) => "pull_request",
+            RulesetRule::RequiredStatusChecks(_) => "required_status_checks",
+            RulesetRule::MergeQueue(_) => "merge_queue",
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for RulesetRule {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::de::Deserializer<'de>,
+    {
+        use serde::de::Error;
+
+        #[derive(Deserialize)]
+        struct RawRule {
+            #[serde(rename = "type")]
+            kind: String,
+            #[serde(default)]
+            parameters: Option<serde_json::Value>,
+        }
+        let raw = RawRule::deserialize(deserializer)?;
+        let parameters = raw.parameters.unwrap_or_else(|| serde_json::json!({}));
+        macro_rules! parameters {
+            ($variant:ident) => {
+                RulesetRule::$variant(serde_json::from_value(parameters).map_err(D::Error::custom)?)
+            };
+        }
+        Ok(match raw.kind.as_str() {
+            "creation" => RulesetRule::Creation,
+            "update" => parameters!(Update),
+            "deletion" => RulesetRule::Deletion,
+            "required_linear_history" => RulesetRule::RequiredLinearHistory,
+            "required_signatures" => RulesetRule::RequiredSignatures,
+            "non_fast_forward" => RulesetRule::NonFastForward,
+            "pull_request" => parameters!(PullRequest),
+            "required_status_checks" => parameters!(RequiredStatusChecks),
+            "merge_queue" => parameters!(MergeQueue),
+            other => {
+                return Err(D::Error::custom(format!(
+                    "unsupported ruleset rule `{other}`"
+                )))
+            }
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub(crate) struct UpdateRuleParameters {
+    /// Whether branches can be updated from their base branch by users without bypass permission
+    pub(crate) update_allows_fetch_and_merge: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub(crate) struct PullRequestRuleParameters {
+    pub(crate) required_approving_review_count: u8,
+    pub(crate) dismiss_stale_reviews_on_push: bool,
+    pub(crate) require_code_owner_review: bool,
+    pub(crate) require_last_push_approval: bool,
+    pub(crate) required_review_thread_resolution: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub(crate) struct RequiredStatusChecksRuleParameters {
+    pub(crate) required_status_checks: Vec<RulesetStatusCheck>,
+    /// Whether branches must be up to date with the base branch before merging
+    pub(crate) strict_required_status_checks_policy: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub(crate) struct RulesetStatusCheck {
+    pub(crate) context: String,
+    /// ID of the app expected to report the check, any app is accepted otherwise.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) integration_id: Option<u64>,
+}
+
+/// Parameters of the merge queue. The defaults are the ones of the GitHub UI.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub(crate) struct MergeQueueRuleParameters {
+    pub(crate) check_response_timeout_minutes: u32,
+    pub(crate) grouping_strategy: MergeQueueGroupingStrategy,
+    pub(crate) max_entries_to_build: u32,
+    pub(crate) max_entries_to_merge: u32,
+    pub(crate) merge_method: MergeQueueMergeMethod,
+    pub(crate) min_entries_to_merge: u32,
+    pub(crate) min_entries_to_merge_wait_minutes: u32,
+}
+
+impl Default for MergeQueueRuleParameters {
+    fn default() -> Self {
+        Self {
+            check_response_timeout_minutes: 60,
+            grouping_strategy: MergeQueueGroupingStrategy::Allgreen,
+            max_entries_to_build: 5,
+            max_entries_to_merge: 5,
+            merge_method: MergeQueueMergeMethod::Merge,
+            min_entries_to_merge: 1,
+            min_entries_to_merge_wait_minutes: 5,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub(crate) enum MergeQueueGroupingStrategy {
+    /// All the pull requests of a group must pass the checks
+    Allgreen,
+    /// Only the head of a group must pass the checks
+    Headgreen,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub(crate) enum MergeQueueMergeMethod {
+    Merge,
+    Squash,
+    Rebase,
+}
+
+#[derive(PartialEq, serde::Serialize)]
 pub(crate) struct RepoSettings {
     pub description: Option<String>,
     pub homepage: Option<String>,
     pub archived: bool,
     pub auto_merge_enabled: bool,
+    pub options: RepoOptions,
+}
+
+/// Settings of a repo that are only managed when configured. `None` leaves the current value
+/// untouched, or GitHub's default when creating the repo.
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub(crate) struct RepoOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) allow_merge_commit: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) allow_squash_merge: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) allow_rebase_merge: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) delete_branch_on_merge: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) has_issues: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) has_wiki: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) has_projects: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) has_discussions: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) visibility: Option<RepoVisibility>,
+}
+
+impl RepoOptions {
+    /// The options configured in `config`, falling back to the current ones.
+    pub(crate) fn overridden_by(&self, config: &RepoOptions) -> RepoOptions {
+        RepoOptions {
+            allow_merge_commit: config.allow_merge_commit.or(self.allow_merge_commit),
+            allow_squash_merge: config.allow_squash_merge.or(self.allow_squash_merge),
+            allow_rebase_merge: config.allow_rebase_merge.or(self.allow_rebase_merge),
+            delete_branch_on_merge: config
+                .delete_branch_on_merge
+                .or(self.delete_branch_on_merge),
+            has_issues: config.has_issues.or(self.has_issues),
+            has_wiki: config.has_wiki.or(self.has_wiki),
+            has_projects: config.has_projects.or(self.has_projects),
+            has_discussions: config.has_discussions.or(self.has_discussions),
+            visibility: config.visibility.or(self.visibility),
+        }
+    }
+
+    /// The options that are set and differ from the `current` ones.
+    pub(crate) fn changed_from(&self, current: &RepoOptions) -> RepoOptions {
+        fn changed<T: PartialEq>(new: Option<T>, current: &Option<T>) -> Option<T> {
+            new.filter(|new| current.as_ref() != Some(new))
+        }
+        RepoOptions {
+            allow_merge_commit: changed(self.allow_merge_commit, &current.allow_merge_commit),
+            allow_squash_merge: changed(self.allow_squash_merge, &current.allow_squash_merge),
+            allow_rebase_merge: changed(self.allow_rebase_merge, &current.allow_rebase_merge),
+            delete_branch_on_merge: changed(
+                self.delete_branch_on_merge,
+                &current.delete_branch_on_merge,
+            ),
+            has_issues: changed(self.has_issues, &current.has_issues),
+            has_wiki: changed(self.has_wiki, &current.has_wiki),
+            has_projects: changed(self.has_projects, &current.has_projects),
+            has_discussions: changed(self.has_discussions, &current.has_discussions),
+            visibility: changed(self.visibility, &current.visibility),
+        }
+    }
+}
+
+/// Settings of a repo that are only applied when creating it
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct RepoCreationSettings {
+    /// The `org/name` of the template repository the repo is generated from.
+    pub(crate) template: Option<String>,
+    /// Name of the default branch, instead of the one of the org or of the template.
+    pub(crate) default_branch: Option<String>,
+    /// License added to the initial commit, like `mit` (not supported with a template).
+    pub(crate) license_template: Option<String>,
+    /// `.gitignore` added to the initial commit, like `Rust` (not supported with a template).
+    pub(crate) gitignore_template: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RepoVisibility {
+    // The GraphQL API uses uppercase names.
+    #[serde(alias = "PUBLIC")]
+    Public,
+    #[serde(alias = "PRIVATE")]
+    Private,
+    /// Only visible to the members of the enterprise owning the org.
+    #[serde(alias = "INTERNAL")]
+    Internal,
+}
+
+impl std::fmt::Display for RepoVisibility {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RepoVisibility::Public => write!(f, "public"),
+            RepoVisibility::Private => write!(f, "private"),
+            RepoVisibility::Internal => write!(f, "internal"),
+        }
+    }
 }
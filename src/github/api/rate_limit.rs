@@ -0,0 +1,129 @@
+//! Tracking of the GitHub API rate limits.
+//!
+//! GitHub reports the remaining budget of each rate limit resource (`core` for the REST API,
+//! `graphql` for the GraphQL API) in the headers of every response. We record it to wait for the
+//! budget to reset once it's exhausted, instead of failing halfway through a sync. Requests
+//! rejected by a secondary rate limit are retried after the delay requested by GitHub.
+
+use log::warn;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How many times a request rejected by a rate limit is retried before giving up.
+pub(super) const MAX_RETRIES: u32 = 5;
+
+/// How long to wait after hitting a secondary rate limit when GitHub doesn't tell us. GitHub
+/// recommends waiting at least one minute, and to increase the delay exponentially afterwards.
+const SECONDARY_RATE_LIMIT_WAIT: Duration = Duration::from_secs(60);
+
+const RESOURCE: &str = "x-ratelimit-resource";
+const REMAINING: &str = "x-ratelimit-remaining";
+const RESET: &str = "x-ratelimit-reset";
+
+/// Remaining budget of a rate limit resource.
+#[derive(Clone, Copy, Debug)]
+struct Budget {
+    remaining: u64,
+    /// When the budget resets, in seconds since the UNIX epoch.
+    reset: u64,
+}
+
+/// Rate limit budgets of the resources used so far, shared between the clones of a `HttpClient`.
+#[derive(Default, Debug)]
+pub(super) struct RateLimits {
+    budgets: Mutex<HashMap<String, Budget>>,
+}
+
+impl RateLimits {
+    /// Block until the budget of `resource` resets, if it was exhausted by previous requests.
+    pub(super) fn wait_for_budget(&self, resource: &str) {
+        let budget = self.budgets.lock().unwrap().get(resource).copied();
+        if let Some(Budget {
+            remaining: 0,
+            reset,
+        }) = budget
+        {
+            let delay = until(reset);
+            if !delay.is_zero() {
+                warn!(
+                    "the GitHub {resource} rate limit is exhausted, waiting {}s for it to reset",
+                    delay.as_secs()
+                );
+                std::thread::sleep(delay);
+            }
+            self.budgets.lock().unwrap().remove(resource);
+        }
+    }
+
+    /// Record the budget reported in the headers of a response.
+    pub(super) fn update(&self, headers: &HeaderMap) {
+        let (Some(resource), Some(remaining), Some(reset)) = (
+            header(headers, RESOURCE),
+            header(headers, REMAINING).and_then(|v| v.parse().ok()),
+            header(headers, RESET).and_then(|v| v.parse().ok()),
+        ) else {
+            return;
+        };
+        self.budgets
+            .lock()
+            .unwrap()
+            .insert(resource.to_string(), Budget { remaining, reset });
+    }
+}
+
+/// Return how long to wait before retrying a request, if the response was rejected by a rate
+/// limit. `attempt` is the number of retries already made, used to back off exponentially.
+pub(super) fn retry_delay(
+    status: StatusCode,
+    headers: &HeaderMap,
+    body: &str,
+    attempt: u32,
+) -> Option<Duration> {
+    if status != StatusCode::FORBIDDEN && status != StatusCode::TOO_MANY_REQUESTS {
+        return None;
+    }
+    if let Some(seconds) = header(headers, RETRY_AFTER.as_str()).and_then(|v| v.parse().ok()) {
+        Some(Duration::from_secs(seconds))
+    } else if header(headers, REMAINING) == Some("0") {
+        let reset = header(headers, RESET).and_then(|v| v.parse().ok())?;
+        Some(until(reset))
+    } else if status == StatusCode::TOO_MANY_REQUESTS
+        || body.to_lowercase().contains("secondary rate limit")
+    {
+        Some(SECONDARY_RATE_LIMIT_WAIT * 2u32.pow(attempt))
+    } else {
+        None
+    }
+}
+
+/// Return how long to wait before retrying a GraphQL query rejected with a `RATE_LIMITED` error,
+/// which GitHub reports in a successful response. `attempt` is the number of retries already made.
+pub(super) fn graphql_retry_delay(headers: &HeaderMap, attempt: u32) -> Duration {
+    if let Some(seconds) = header(headers, RETRY_AFTER.as_str()).and_then(|v| v.parse().ok()) {
+        return Duration::from_secs(seconds);
+    }
+    match header(headers, RESET).and_then(|v| v.parse().ok()) {
+        Some(reset) if header(headers, REMAINING) == Some("0") => until(reset),
+        _ => SECONDARY_RATE_LIMIT_WAIT * 2u32.pow(attempt),
+    }
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name)?.to_str().ok()
+}
+
+/// Duration until the given UNIX timestamp, with a second of margin for clock skew.
+fn until(timestamp: u64) -> Duration {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    if timestamp < now {
+        Duration::ZERO
+    } else {
+        Duration::from_secs(timestamp - now + 1)
+    }
+}
@@ -1,18 +1,30 @@
 use crate::github::api::{
-    team_node_id, user_node_id, BranchProtection, GraphNode, GraphNodes, GraphPageInfo, HttpClient,
-    Login, OrgAppInstallation, Repo, RepoAppInstallation, RepoTeam, RepoUser, Team, TeamMember,
-    TeamRole,
+    graphql_permission, team_node_id, user_node_id, BranchProtection, GraphNode, GraphNodes,
+    GraphPageInfo, HttpClient, Login, OrgAppInstallation, PushAllowanceActor, Repo,
+    RepoAppInstallation, RepoOptions, RepoPermission, RepoState, RepoTeam, RepoUser,
+    RepoVisibility, Ruleset, Team, TeamMember, TeamRole,
 };
 use reqwest::Method;
 use std::collections::{HashMap, HashSet};
 
-pub(crate) trait GithubRead {
+/// Reads the current state of GitHub. Implementations are shared between the threads fetching the
+/// state of multiple teams and repositories at the same time.
+pub(crate) trait GithubRead: Send + Sync {
     /// Get user names by user ids
     fn usernames(&self, ids: &[u64]) -> anyhow::Result<HashMap<u64, String>>;
 
     /// Get the owners of an org
     fn org_owners(&self, org: &str) -> anyhow::Result<HashSet<u64>>;
 
+    /// Get the members of an org, keyed by user id
+    fn org_members(&self, org: &str) -> anyhow::Result<HashMap<u64, String>>;
+
+    /// The GitHub names of users invited to the given org
+    fn org_invitations(&self, org: &str) -> anyhow::Result<HashSet<String>>;
+
+    /// Get all the repos of an org
+    fn org_repositories(&self, org: &str) -> anyhow::Result<Vec<Repo>>;
+
     /// Get the app installations of an org
     fn org_app_installations(&self, org: &str) -> anyhow::Result<Vec<OrgAppInstallation>>;
 
@@ -53,6 +65,168 @@ pub(crate) trait GithubRead {
         org: &str,
         repo: &str,
     ) -> anyhow::Result<HashMap<String, (String, BranchProtection)>>;
+
+    /// Get the topics of a repo
+    fn repo_topics(&self, org: &str, repo: &str) -> anyhow::Result<Vec<String>>;
+
+    /// Get the rulesets of a repo by name, along with their IDs
+    ///
+    /// The rulesets defined by the org and applying to the repo are not included.
+    fn repo_rulesets(
+        &self,
+        org: &str,
+        repo: &str,
+    ) -> anyhow::Result<HashMap<String, (u64, Ruleset)>>;
+
+    /// Get the state of a repo by org and name
+    fn repo_state(&self, org: &str, repo: &str) -> anyhow::Result<Option<RepoState>> {
+        let Some(actual_repo) = self.repo(org, repo)? else {
+            return Ok(None);
+        };
+        Ok(Some(RepoState {
+            teams: self.repo_teams(org, repo)?,
+            collaborators: self.repo_collaborators(org, repo)?,
+            branch_protections: self.branch_protections(&actual_repo.org, &actual_repo.name)?,
+            topics: self.repo_topics(&actual_repo.org, &actual_repo.name)?,
+            repo: actual_repo,
+        }))
+    }
+
+    /// Get the state of multiple repos of an org, keyed by the requested names
+    ///
+    /// Repos that don't exist are missing from the result. The repos are fetched one by one by
+    /// default, implementations can override this with a more efficient way of reading them.
+    fn repos_state(&self, org: &str, repos: &[&str]) -> anyhow::Result<HashMap<String, RepoState>> {
+        let mut states = HashMap::new();
+        for repo in repos {
+            if let Some(state) = self.repo_state(org, repo)? {
+                states.insert(repo.to_string(), state);
+            }
+        }
+        Ok(states)
+    }
+}
+
+/// Fields of a `PushAllowance` or `ReviewDismissalAllowance` deserialized into a
+/// `PushAllowanceActor`.
+macro_rules! push_allowance_fields {
+    () => {
+        "
+                                    actor {
+                                        ... on Actor {
+                                            login
+                                        }
+                                        ... on Team {
+                                            organization {
+                                                login
+                                            },
+                                            name
+                                        }
+                                    }
+        "
+    };
+}
+
+/// Fields of a `BranchProtectionRule` deserialized into a `BranchProtectionWrapper`, shared by
+/// the queries reading branch protections. The first page of push and review dismissal allowances
+/// is included, the following ones have to be fetched with `GitHubApiRead::complete_allowances`.
+macro_rules! branch_protection_fields {
+    () => {
+        concat!(
+            "
+                            id,
+                            pattern,
+                            isAdminEnforced,
+                            dismissesStaleReviews,
+                            requiredStatusChecks {
+                                context
+                                app {
+                                    databaseId
+                                }
+                            }
+                            requiredApprovingReviewCount,
+                            requiresApprovingReviews
+                            requiresStrictStatusChecks
+                            requiresCodeOwnerReviews
+                            requiresLinearHistory
+                            requiresConversationResolution
+                            allowsForcePushes
+                            allowsDeletions
+                            reviewDismissalAllowances(first: 100) {
+                                pageInfo {
+                                    endCursor
+                                    hasNextPage
+                                }
+                                nodes {",
+            push_allowance_fields!(),
+            "
+                                }
+                            }
+                            pushAllowances(first: 100) {
+                                pageInfo {
+                                    endCursor
+                                    hasNextPage
+                                }
+                                nodes {",
+            push_allowance_fields!(),
+            "
+                                }
+                            }
+        "
+        )
+    };
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BranchProtectionWrapper {
+    id: String,
+    push_allowances: AllowancesPage,
+    review_dismissal_allowances: AllowancesPage,
+    #[serde(flatten)]
+    protection: BranchProtection,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AllowancesPage {
+    page_info: GraphPageInfo,
+    #[serde(default)]
+    nodes: Vec<AllowanceNode>,
+}
+
+#[derive(serde::Deserialize)]
+struct AllowanceNode {
+    actor: PushAllowanceActor,
+}
+
+/// Query of the next page of allowances of a branch protection rule, where `$field` is the
+/// connection of `PushAllowance`s or `ReviewDismissalAllowance`s to read.
+macro_rules! allowances_query {
+    ($field:literal) => {
+        concat!(
+            "
+            query($id: ID!, $cursor: String) {
+                node(id: $id) {
+                    ... on BranchProtectionRule {
+                        allowances: ",
+            $field,
+            "(first: 100, after: $cursor) {
+                            pageInfo {
+                                endCursor
+                                hasNextPage
+                            }
+                            nodes {",
+            push_allowance_fields!(),
+            "
+                            }
+                        }
+                    }
+                }
+            }
+        "
+        )
+    };
 }
 
 pub(crate) struct GitHubApiRead {
@@ -120,6 +294,55 @@ impl GithubRead for GitHubApiRead {
         Ok(owners)
     }
 
+    fn org_members(&self, org: &str) -> anyhow::Result<HashMap<u64, String>> {
+        #[derive(serde::Deserialize)]
+        struct User {
+            id: u64,
+            login: String,
+        }
+        let mut members = HashMap::new();
+        self.client.rest_paginated(
+            &Method::GET,
+            format!("orgs/{org}/members"),
+            |resp: Vec<User>| {
+                members.extend(resp.into_iter().map(|u| (u.id, u.login)));
+                Ok(())
+            },
+        )?;
+        Ok(members)
+    }
+
+    fn org_invitations(&self, org: &str) -> anyhow::Result<HashSet<String>> {
+        #[derive(serde::Deserialize)]
+        struct Invitation {
+            // Users invited by email don't have a login.
+            login: Option<String>,
+        }
+        let mut invites = HashSet::new();
+        self.client.rest_paginated(
+            &Method::GET,
+            format!("orgs/{org}/invitations"),
+            |resp: Vec<Invitation>| {
+                invites.extend(resp.into_iter().filter_map(|i| i.login));
+                Ok(())
+            },
+        )?;
+        Ok(invites)
+    }
+
+    fn org_repositories(&self, org: &str) -> anyhow::Result<Vec<Repo>> {
+        let mut repos = Vec::new();
+        self.client.rest_paginated(
+            &Method::GET,
+            format!("orgs/{org}/repos?per_page=100"),
+            |resp: Vec<Repo>| {
+                repos.extend(resp);
+                Ok(())
+            },
+        )?;
+        Ok(repos)
+    }
+
     fn org_app_installations(&self, org: &str) -> anyhow::Result<Vec<OrgAppInstallation>> {
         #[derive(serde::Deserialize, Debug)]
         struct InstallationPage {
@@ -321,68 +544,583 @@ impl GithubRead for GitHubApiRead {
         struct Params<'a> {
             org: &'a str,
             repo: &'a str,
+            cursor: Option<&'a str>,
         }
-        static QUERY: &str = "
-            query($org:String!,$repo:String!) {
+        static QUERY: &str = concat!(
+            "
+            query($org:String!,$repo:String!,$cursor:String) {
                 repository(owner:$org, name:$repo) {
-                    branchProtectionRules(first:100) {
-                        nodes { 
-                            id,
-                            pattern,
-                            isAdminEnforced,
-                            dismissesStaleReviews,
-                            requiredStatusCheckContexts,
-                            requiredApprovingReviewCount,
-                            requiresApprovingReviews
-                            pushAllowances(first: 100) {
+                    branchProtectionRules(first:100, after:$cursor) {
+                        pageInfo {
+                            endCursor
+                            hasNextPage
+                        }
+                        nodes {",
+            branch_protection_fields!(),
+            "
+                         }
+                    }
+                }
+            }
+        "
+        );
+
+        #[derive(serde::Deserialize)]
+        struct Wrapper {
+            repository: Respository,
+        }
+        #[derive(serde::Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Respository {
+            branch_protection_rules: BranchProtectionRules,
+        }
+        #[derive(serde::Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct BranchProtectionRules {
+            page_info: GraphPageInfo,
+            nodes: Vec<Option<BranchProtectionWrapper>>,
+        }
+
+        let mut result = HashMap::new();
+        let mut page_info = GraphPageInfo::start();
+        while page_info.has_next_page {
+            let res: Wrapper = self.client.graphql(
+                QUERY,
+                Params {
+                    org,
+                    repo,
+                    cursor: page_info.end_cursor.as_deref(),
+                },
+            )?;
+            let rules = res.repository.branch_protection_rules;
+            page_info = rules.page_info;
+            for rule in rules.nodes.into_iter().flatten() {
+                let (id, protection) = self.complete_allowances(rule)?;
+                result.insert(protection.pattern.clone(), (id, protection));
+            }
+        }
+        Ok(result)
+    }
+
+    fn repo_topics(&self, org: &str, repo: &str) -> anyhow::Result<Vec<String>> {
+        #[derive(serde::Deserialize)]
+        struct Topics {
+            names: Vec<String>,
+        }
+        let topics: Option<Topics> = self
+            .client
+            .send_option(Method::GET, &format!("repos/{org}/{repo}/topics"))?;
+        Ok(topics.map(|t| t.names).unwrap_or_default())
+    }
+
+    fn repo_rulesets(
+        &self,
+        org: &str,
+        repo: &str,
+    ) -> anyhow::Result<HashMap<String, (u64, Ruleset)>> {
+        #[derive(serde::Deserialize)]
+        struct RulesetSummary {
+            id: u64,
+        }
+        // The list of rulesets doesn't include their rules, which are fetched one by one.
+        let mut ids = Vec::new();
+        self.client.rest_paginated(
+            &Method::GET,
+            format!("repos/{org}/{repo}/rulesets?includes_parents=false"),
+            |resp: Vec<RulesetSummary>| {
+                ids.extend(resp.into_iter().map(|r| r.id));
+                Ok(())
+            },
+        )?;
+
+        let mut rulesets = HashMap::new();
+        for id in ids {
+            let ruleset: Option<Ruleset> = self
+                .client
+                .send_option(Method::GET, &format!("repos/{org}/{repo}/rulesets/{id}"))?;
+            if let Some(ruleset) = ruleset {
+                rulesets.insert(ruleset.name.clone(), (id, ruleset.normalized()));
+            }
+        }
+        Ok(rulesets)
+    }
+
+    fn repos_state(&self, org: &str, repos: &[&str]) -> anyhow::Result<HashMap<String, RepoState>> {
+        let mut team_permissions = self.org_team_permissions(org)?;
+
+        let mut states = HashMap::new();
+        for bulk in self.org_repos(org)? {
+            // Repo names are case-insensitive, and the states are keyed by the requested names.
+            let Some(name) = repos
+                .iter()
+                .find(|repo| repo.eq_ignore_ascii_case(&bulk.repo.name))
+            else {
+                continue;
+            };
+            let name = name.to_string();
+            let state = if bulk.truncated {
+                // Some of the state of the repo didn't fit in the bulk query.
+                match self.repo_state(org, &name)? {
+                    Some(state) => state,
+                    None => continue,
+                }
+            } else {
+                RepoState {
+                    teams: team_permissions.remove(&bulk.repo.name).unwrap_or_default(),
+                    repo: bulk.repo,
+                    collaborators: bulk.collaborators,
+                    branch_protections: bulk.branch_protections,
+                    topics: bulk.topics,
+                }
+            };
+            states.insert(name, state);
+        }
+
+        // Repos missing from the org might have been renamed, which only the REST API follows.
+        for repo in repos {
+            if !states.contains_key(*repo) {
+                if let Some(state) = self.repo_state(org, repo)? {
+                    states.insert(repo.to_string(), state);
+                }
+            }
+        }
+
+        Ok(states)
+    }
+}
+
+/// A repo read in bulk with all the other repos of its org
+struct BulkRepo {
+    repo: Repo,
+    collaborators: Vec<RepoUser>,
+    branch_protections: HashMap<String, (String, BranchProtection)>,
+    topics: Vec<String>,
+    /// Whether some of the collaborators or branch protections didn't fit in the query.
+    truncated: bool,
+}
+
+impl GitHubApiRead {
+    /// Get all the repos of an org, with their direct collaborators and branch protections
+    fn org_repos(&self, org: &str) -> anyhow::Result<Vec<BulkRepo>> {
+        #[derive(serde::Serialize)]
+        struct Params<'a> {
+            org: &'a str,
+            cursor: Option<&'a str>,
+        }
+        // The page size is kept small to stay below the maximum number of nodes of a query.
+        static QUERY: &str = concat!(
+            "
+            query($org: String!, $cursor: String) {
+                organization(login: $org) {
+                    repositories(first: 25, after: $cursor) {
+                        pageInfo {
+                            endCursor
+                            hasNextPage
+                        }
+                        nodes {
+                            id
+                            databaseId
+                            name
+                            description
+                            homepageUrl
+                            isArchived
+                            autoMergeAllowed
+                            mergeCommitAllowed
+                            squashMergeAllowed
+                            rebaseMergeAllowed
+                            deleteBranchOnMerge
+                            hasIssuesEnabled
+                            hasWikiEnabled
+                            hasProjectsEnabled
+                            hasDiscussionsEnabled
+                            visibility
+                            repositoryTopics(first: 20) {
                                 nodes {
-                                    actor {
-                                        ... on Actor {
-                                            login
-                                        }
-                                        ... on Team {
-                                            organization {
-                                                login
-                                            },
-                                            name
-                                        }
+                                    topic {
+                                        name
                                     }
                                 }
                             }
-                         }
+                            collaborators(affiliation: DIRECT, first: 100) {
+                                pageInfo {
+                                    hasNextPage
+                                }
+                                edges {
+                                    permission
+                                    node {
+                                        login
+                                    }
+                                }
+                            }
+                            branchProtectionRules(first: 100) {
+                                pageInfo {
+                                    hasNextPage
+                                }
+                                nodes {",
+            branch_protection_fields!(),
+            "
+                                }
+                            }
+                        }
                     }
                 }
             }
-        ";
+        "
+        );
 
         #[derive(serde::Deserialize)]
         struct Wrapper {
-            repository: Respository,
+            organization: Organization,
+        }
+        #[derive(serde::Deserialize)]
+        struct Organization {
+            repositories: Repositories,
         }
         #[derive(serde::Deserialize)]
         #[serde(rename_all = "camelCase")]
-        struct Respository {
-            branch_protection_rules: GraphNodes<BranchProtectionWrapper>,
+        struct Repositories {
+            page_info: GraphPageInfo,
+            nodes: Vec<RepoNode>,
         }
         #[derive(serde::Deserialize)]
         #[serde(rename_all = "camelCase")]
-        struct BranchProtectionWrapper {
+        struct RepoNode {
             id: String,
-            #[serde(flatten)]
-            protection: BranchProtection,
+            database_id: u64,
+            name: String,
+            description: Option<String>,
+            homepage_url: Option<String>,
+            is_archived: bool,
+            auto_merge_allowed: bool,
+            merge_commit_allowed: Option<bool>,
+            squash_merge_allowed: Option<bool>,
+            rebase_merge_allowed: Option<bool>,
+            delete_branch_on_merge: Option<bool>,
+            has_issues_enabled: Option<bool>,
+            has_wiki_enabled: Option<bool>,
+            has_projects_enabled: Option<bool>,
+            has_discussions_enabled: Option<bool>,
+            visibility: Option<RepoVisibility>,
+            // GitHub doesn't allow more than 20 topics per repo, so they're never truncated.
+            #[serde(default)]
+            repository_topics: Option<RepositoryTopics>,
+            collaborators: Option<Collaborators>,
+            branch_protection_rules: BranchProtectionRules,
+        }
+        #[derive(serde::Deserialize)]
+        struct RepositoryTopics {
+            nodes: Vec<RepositoryTopic>,
+        }
+        #[derive(serde::Deserialize)]
+        struct RepositoryTopic {
+            topic: Topic,
+        }
+        #[derive(serde::Deserialize)]
+        struct Topic {
+            name: String,
+        }
+        #[derive(serde::Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Collaborators {
+            page_info: GraphPageInfo,
+            edges: Vec<CollaboratorEdge>,
+        }
+        #[derive(serde::Deserialize)]
+        struct CollaboratorEdge {
+            #[serde(deserialize_with = "graphql_permission")]
+            permission: RepoPermission,
+            node: Login,
+        }
+        #[derive(serde::Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct BranchProtectionRules {
+            page_info: GraphPageInfo,
+            nodes: Vec<BranchProtectionWrapper>,
         }
 
-        let mut result = HashMap::new();
-        let res: Wrapper = self.client.graphql(QUERY, Params { org, repo })?;
-        for node in res
-            .repository
-            .branch_protection_rules
+        let mut repos = Vec::new();
+        let mut page_info = GraphPageInfo::start();
+        while page_info.has_next_page {
+            let res: Wrapper = self.client.graphql(
+                QUERY,
+                Params {
+                    org,
+                    cursor: page_info.end_cursor.as_deref(),
+                },
+            )?;
+            page_info = res.organization.repositories.page_info;
+            for node in res.organization.repositories.nodes {
+                let rules = node.branch_protection_rules;
+                let truncated = node
+                    .collaborators
+                    .as_ref()
+                    .map_or(true, |c| c.page_info.has_next_page)
+                    || rules.page_info.has_next_page;
+                let mut branch_protections = HashMap::new();
+                for rule in rules.nodes {
+                    let (id, protection) = self.complete_allowances(rule)?;
+                    branch_protections.insert(protection.pattern.clone(), (id, protection));
+                }
+                repos.push(BulkRepo {
+                    repo: Repo {
+                        node_id: node.id,
+                        repo_id: node.database_id,
+                        name: node.name,
+                        org: org.to_string(),
+                        description: node.description,
+                        homepage: node.homepage_url.filter(|homepage| !homepage.is_empty()),
+                        archived: node.is_archived,
+                        allow_auto_merge: Some(node.auto_merge_allowed),
+                        options: RepoOptions {
+                            allow_merge_commit: node.merge_commit_allowed,
+                            allow_squash_merge: node.squash_merge_allowed,
+                            allow_rebase_merge: node.rebase_merge_allowed,
+                            delete_branch_on_merge: node.delete_branch_on_merge,
+                            has_issues: node.has_issues_enabled,
+                            has_wiki: node.has_wiki_enabled,
+                            has_projects: node.has_projects_enabled,
+                            has_discussions: node.has_discussions_enabled,
+                            visibility: node.visibility,
+                        },
+                    },
+                    collaborators: node
+                        .collaborators
+                        .map(|c| c.edges)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|edge| RepoUser {
+                            name: edge.node.login,
+                            permission: edge.permission,
+                        })
+                        .collect(),
+                    branch_protections,
+                    topics: node
+                        .repository_topics
+                        .map(|t| t.nodes)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|node| node.topic.name)
+                        .collect(),
+                    truncated,
+                });
+            }
+        }
+        Ok(repos)
+    }
+
+    /// Fetch the allowances of a branch protection rule that didn't fit in the first page
+    fn complete_allowances(
+        &self,
+        rule: BranchProtectionWrapper,
+    ) -> anyhow::Result<(String, BranchProtection)> {
+        let BranchProtectionWrapper {
+            id,
+            push_allowances,
+            review_dismissal_allowances,
+            mut protection,
+        } = rule;
+        protection.push_allowances =
+            self.allowances(&id, allowances_query!("pushAllowances"), push_allowances)?;
+        protection.review_dismissal_allowances = self.allowances(
+            &id,
+            allowances_query!("reviewDismissalAllowances"),
+            review_dismissal_allowances,
+        )?;
+        Ok((id, protection))
+    }
+
+    /// Collect the actors of all the pages of allowances read by `query`, from the first `page`
+    fn allowances(
+        &self,
+        id: &str,
+        query: &str,
+        mut page: AllowancesPage,
+    ) -> anyhow::Result<Vec<PushAllowanceActor>> {
+        #[derive(serde::Serialize)]
+        struct Params<'a> {
+            id: &'a str,
+            cursor: Option<&'a str>,
+        }
+        #[derive(serde::Deserialize)]
+        struct Wrapper {
+            node: Node,
+        }
+        #[derive(serde::Deserialize)]
+        struct Node {
+            allowances: AllowancesPage,
+        }
+
+        let mut actors = page
             .nodes
-            .into_iter()
-            .flatten()
-        {
-            result.insert(node.protection.pattern.clone(), (node.id, node.protection));
+            .drain(..)
+            .map(|node| node.actor)
+            .collect::<Vec<_>>();
+        while page.page_info.has_next_page {
+            let res: Wrapper = self.client.graphql(
+                query,
+                Params {
+                    id,
+                    cursor: page.page_info.end_cursor.as_deref(),
+                },
+            )?;
+            page = res.node.allowances;
+            actors.extend(page.nodes.drain(..).map(|node| node.actor));
         }
-        Ok(result)
+        Ok(actors)
+    }
+
+    /// Get the permissions of all the teams of an org, keyed by repo name
+    fn org_team_permissions(&self, org: &str) -> anyhow::Result<HashMap<String, Vec<RepoTeam>>> {
+        #[derive(serde::Serialize)]
+        struct Params<'a> {
+            org: &'a str,
+            cursor: Option<&'a str>,
+        }
+        #[derive(serde::Serialize)]
+        struct TeamParams<'a> {
+            org: &'a str,
+            team: &'a str,
+            cursor: Option<&'a str>,
+        }
+        static QUERY: &str = "
+            query($org: String!, $cursor: String) {
+                organization(login: $org) {
+                    teams(first: 50, after: $cursor) {
+                        pageInfo {
+                            endCursor
+                            hasNextPage
+                        }
+                        nodes {
+                            name
+                            slug
+                            repositories(first: 100) {
+                                pageInfo {
+                                    endCursor
+                                    hasNextPage
+                                }
+                                edges {
+                                    permission
+                                    node {
+                                        name
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        ";
+        static TEAM_QUERY: &str = "
+            query($org: String!, $team: String!, $cursor: String) {
+                organization(login: $org) {
+                    team(slug: $team) {
+                        repositories(first: 100, after: $cursor) {
+                            pageInfo {
+                                endCursor
+                                hasNextPage
+                            }
+                            edges {
+                                permission
+                                node {
+                                    name
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        ";
+
+        #[derive(serde::Deserialize)]
+        struct Wrapper<T> {
+            organization: T,
+        }
+        #[derive(serde::Deserialize)]
+        struct Organization {
+            teams: Teams,
+        }
+        #[derive(serde::Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Teams {
+            page_info: GraphPageInfo,
+            nodes: Vec<TeamNode>,
+        }
+        #[derive(serde::Deserialize)]
+        struct TeamNode {
+            name: String,
+            slug: String,
+            repositories: Repositories,
+        }
+        #[derive(serde::Deserialize)]
+        struct TeamOrganization {
+            team: Option<TeamRepositories>,
+        }
+        #[derive(serde::Deserialize)]
+        struct TeamRepositories {
+            repositories: Repositories,
+        }
+        #[derive(serde::Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Repositories {
+            page_info: GraphPageInfo,
+            edges: Vec<RepositoryEdge>,
+        }
+        #[derive(serde::Deserialize)]
+        struct RepositoryEdge {
+            #[serde(deserialize_with = "graphql_permission")]
+            permission: RepoPermission,
+            node: RepositoryName,
+        }
+        #[derive(serde::Deserialize)]
+        struct RepositoryName {
+            name: String,
+        }
+
+        let mut permissions: HashMap<String, Vec<RepoTeam>> = HashMap::new();
+        let mut add_permissions = |team: &str, edges: Vec<RepositoryEdge>| {
+            for edge in edges {
+                permissions
+                    .entry(edge.node.name)
+                    .or_default()
+                    .push(RepoTeam {
+                        name: team.to_string(),
+                        permission: edge.permission,
+                    });
+            }
+        };
+
+        let mut page_info = GraphPageInfo::start();
+        while page_info.has_next_page {
+            let res: Wrapper<Organization> = self.client.graphql(
+                QUERY,
+                Params {
+                    org,
+                    cursor: page_info.end_cursor.as_deref(),
+                },
+            )?;
+            page_info = res.organization.teams.page_info;
+            for team in res.organization.teams.nodes {
+                let mut repos_page_info = team.repositories.page_info;
+                add_permissions(&team.name, team.repositories.edges);
+
+                // Teams with access to many repos need more queries to read all of them.
+                while repos_page_info.has_next_page {
+                    let res: Wrapper<TeamOrganization> = self.client.graphql(
+                        TEAM_QUERY,
+                        TeamParams {
+                            org,
+                            team: &team.slug,
+                            cursor: repos_page_info.end_cursor.as_deref(),
+                        },
+                    )?;
+                    let Some(repos) = res.organization.team.map(|t| t.repositories) else {
+                        break;
+                    };
+                    repos_page_info = repos.page_info;
+                    add_permissions(&team.name, repos.edges);
+                }
+            }
+        }
+        Ok(permissions)
     }
 }
@@ -0,0 +1,618 @@
+use super::{
+    BranchProtection, BranchProtectionOp, GitHubApiRead, GitHubWrite, GithubRead, HttpClient,
+    PushAllowanceActor, RepoCreationSettings, RepoOptions, RepoPermission, RepoSettings,
+    RepoVisibility, RequiredStatusCheck, RulesetActorType, RulesetRule,
+};
+use crate::mock_server::{MockResponse, MockServer};
+use crate::retry::RetryPolicy;
+use reqwest::Method;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const SECONDARY_RATE_LIMIT: &str =
+    r#"{"message": "You have exceeded a secondary rate limit. Please wait a few minutes."}"#;
+
+fn client(server: &MockServer) -> HttpClient {
+    HttpClient::from_url_and_token(
+        server.url().to_string(),
+        "token".to_string(),
+        RetryPolicy::default(),
+    )
+    .unwrap()
+}
+
+#[test]
+fn retry_idempotent_request_on_secondary_rate_limit() {
+    let server = MockServer::start(vec![
+        MockResponse::new(403, SECONDARY_RATE_LIMIT).header("retry-after", "0"),
+        MockResponse::new(200, r#"{"login": "octocat"}"#),
+    ]);
+    let user: Option<serde_json::Value> = client(&server)
+        .send_option(Method::GET, "users/octocat")
+        .unwrap();
+    assert_eq!(user.unwrap()["login"], "octocat");
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[test]
+fn do_not_retry_non_idempotent_request() {
+    let server = MockServer::start(vec![
+        MockResponse::new(403, SECONDARY_RATE_LIMIT).header("retry-after", "0")
+    ]);
+    let err = client(&server)
+        .send(Method::POST, "orgs/rust-lang/teams", &"body")
+        .unwrap_err();
+    assert!(err.to_string().contains("403"), "{err}");
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].method, "POST");
+}
+
+#[test]
+fn do_not_retry_permission_errors() {
+    let server = MockServer::start(vec![MockResponse::new(
+        403,
+        r#"{"message": "Must have admin rights to Repository."}"#,
+    )]);
+    let err = client(&server)
+        .send_option::<serde_json::Value>(Method::GET, "repos/rust-lang/rust")
+        .unwrap_err();
+    assert!(err.to_string().contains("admin rights"), "{err}");
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn retry_graphql_query_on_rate_limit() {
+    let server = MockServer::start(vec![
+        MockResponse::new(
+            200,
+            r#"{"errors": [{"type": "RATE_LIMITED", "message": "API rate limit exceeded"}]}"#,
+        )
+        .header("retry-after", "1"),
+        MockResponse::new(200, r#"{"data": {"viewer": {"login": "octocat"}}}"#),
+    ]);
+    let start = Instant::now();
+    let data: serde_json::Value = client(&server)
+        .graphql("query { viewer { login } }", ())
+        .unwrap();
+    assert_eq!(data["viewer"]["login"], "octocat");
+    assert!(start.elapsed() >= Duration::from_millis(500));
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests
+        .iter()
+        .all(|r| r.path == "/graphql" && r.body.contains("viewer")));
+}
+
+#[test]
+fn wait_for_exhausted_budget_to_reset() {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let server = MockServer::start(vec![
+        MockResponse::new(200, "{}")
+            .header("x-ratelimit-resource", "core")
+            .header("x-ratelimit-remaining", "0")
+            .header("x-ratelimit-reset", &(now + 1).to_string()),
+        MockResponse::new(200, "{}"),
+    ]);
+    let client = client(&server);
+    client
+        .send_option::<serde_json::Value>(Method::GET, "rate_limit")
+        .unwrap();
+
+    let start = Instant::now();
+    client
+        .send_option::<serde_json::Value>(Method::GET, "rate_limit")
+        .unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(500));
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[test]
+fn read_repos_state_in_bulk() {
+    let server = MockServer::start(vec![
+        MockResponse::new(
+            200,
+            r#"{"data": {"organization": {"teams": {
+                "pageInfo": {"endCursor": "t1", "hasNextPage": false},
+                "nodes": [{"name": "infra", "slug": "infra", "repositories": {
+                    "pageInfo": {"endCursor": "r1", "hasNextPage": true},
+                    "edges": [{"permission": "ADMIN", "node": {"name": "rust"}}]
+                }}]
+            }}}}"#,
+        ),
+        MockResponse::new(
+            200,
+            r#"{"data": {"organization": {"team": {"repositories": {
+                "pageInfo": {"endCursor": "r2", "hasNextPage": false},
+                "edges": [{"permission": "MAINTAIN", "node": {"name": "crates.io"}}]
+            }}}}}"#,
+        ),
+        MockResponse::new(
+            200,
+            r#"{"data": {"organization": {"repositories": {
+                "pageInfo": {"endCursor": "p1", "hasNextPage": false},
+                "nodes": [{
+                    "id": "R_rust",
+                    "databaseId": 1,
+                    "name": "rust",
+                    "description": "The Rust programming language",
+                    "homepageUrl": null,
+                    "isArchived": false,
+                    "autoMergeAllowed": false,
+                    "repositoryTopics": {"nodes": [{"topic": {"name": "compiler"}}]},
+                    "collaborators": {
+                        "pageInfo": {"hasNextPage": false},
+                        "edges": [{"permission": "WRITE", "node": {"login": "bors"}}]
+                    },
+                    "branchProtectionRules": {
+                        "pageInfo": {"hasNextPage": false},
+                        "nodes": [{
+                            "id": "BPR_master",
+                            "pattern": "master",
+                            "isAdminEnforced": true,
+                            "dismissesStaleReviews": false,
+                            "requiredStatusChecks": [],
+                            "requiredApprovingReviewCount": 1,
+                            "requiresApprovingReviews": true,
+                            "reviewDismissalAllowances": {
+                                "pageInfo": {"endCursor": null, "hasNextPage": false},
+                                "nodes": []
+                            },
+                            "pushAllowances": {
+                                "pageInfo": {"endCursor": "a1", "hasNextPage": false},
+                                "nodes": [{"actor": {"login": "bors"}}]
+                            }
+                        }]
+                    }
+                }]
+            }}}}"#,
+        ),
+        MockResponse::new(404, r#"{"message": "Not Found"}"#),
+    ]);
+    let read = GitHubApiRead::from_client(client(&server)).unwrap();
+    let states = read.repos_state("rust-lang", &["rust", "missing"]).unwrap();
+
+    assert_eq!(states.len(), 1);
+    let rust = &states["rust"];
+    assert_eq!(rust.repo.node_id, "R_rust");
+    assert_eq!(rust.teams.len(), 1);
+    assert_eq!(rust.teams[0].name, "infra");
+    assert_eq!(rust.teams[0].permission, RepoPermission::Admin);
+    assert_eq!(rust.collaborators.len(), 1);
+    assert_eq!(rust.collaborators[0].name, "bors");
+    assert_eq!(rust.collaborators[0].permission, RepoPermission::Write);
+    assert_eq!(rust.branch_protections["master"].0, "BPR_master");
+    assert_eq!(rust.topics, ["compiler"]);
+
+    // Only the repo missing from the org is read with the REST API.
+    let requests = server.requests();
+    assert_eq!(requests.len(), 4);
+    assert_eq!(requests[3].path, "/repos/rust-lang/missing");
+}
+
+#[test]
+fn read_same_repo_state_in_bulk_and_one_by_one() {
+    // GitHub returns the name of the repo in its own case, and the REST API returns an empty
+    // homepage where GraphQL returns null.
+    let rule = branch_protection_rule("master", "bors", false);
+    let bulk = MockServer::start(vec![
+        MockResponse::new(
+            200,
+            r#"{"data": {"organization": {"teams": {
+                "pageInfo": {"endCursor": "t1", "hasNextPage": false},
+                "nodes": [{"name": "infra", "slug": "infra", "repositories": {
+                    "pageInfo": {"endCursor": "r1", "hasNextPage": false},
+                    "edges": [{"permission": "ADMIN", "node": {"name": "Rust"}}]
+                }}]
+            }}}}"#,
+        ),
+        MockResponse::new(
+            200,
+            &format!(
+                r#"{{"data": {{"organization": {{"repositories": {{
+                    "pageInfo": {{"endCursor": "p1", "hasNextPage": false}},
+                    "nodes": [{{
+                        "id": "R_rust",
+                        "databaseId": 1,
+                        "name": "Rust",
+                        "description": "The Rust programming language",
+                        "homepageUrl": null,
+                        "isArchived": false,
+                        "autoMergeAllowed": false,
+                        "mergeCommitAllowed": true,
+                        "squashMergeAllowed": true,
+                        "rebaseMergeAllowed": false,
+                        "deleteBranchOnMerge": true,
+                        "hasIssuesEnabled": true,
+                        "hasWikiEnabled": false,
+                        "hasProjectsEnabled": false,
+                        "hasDiscussionsEnabled": false,
+                        "visibility": "PUBLIC",
+                        "repositoryTopics": {{"nodes": [{{"topic": {{"name": "compiler"}}}}]}},
+                        "collaborators": {{
+                            "pageInfo": {{"hasNextPage": false}},
+                            "edges": [{{"permission": "WRITE", "node": {{"login": "bors"}}}}]
+                        }},
+                        "branchProtectionRules": {{
+                            "pageInfo": {{"hasNextPage": false}},
+                            "nodes": [{rule}]
+                        }}
+                    }}]
+                }}}}}}}}"#
+            ),
+        ),
+    ]);
+    let one_by_one = MockServer::start(vec![
+        MockResponse::new(
+            200,
+            r#"{
+                "node_id": "R_rust",
+                "id": 1,
+                "name": "Rust",
+                "owner": {"login": "rust-lang"},
+                "description": "The Rust programming language",
+                "homepage": "",
+                "archived": false,
+                "allow_auto_merge": false,
+                "allow_merge_commit": true,
+                "allow_squash_merge": true,
+                "allow_rebase_merge": false,
+                "delete_branch_on_merge": true,
+                "has_issues": true,
+                "has_wiki": false,
+                "has_projects": false,
+                "has_discussions": false,
+                "visibility": "public"
+            }"#,
+        ),
+        MockResponse::new(200, r#"[{"name": "infra", "permission": "admin"}]"#),
+        MockResponse::new(200, r#"[{"login": "bors", "role_name": "write"}]"#),
+        MockResponse::new(
+            200,
+            &format!(
+                r#"{{"data": {{"repository": {{"branchProtectionRules": {{
+                    "pageInfo": {{"endCursor": "b1", "hasNextPage": false}},
+                    "nodes": [{rule}]
+                }}}}}}}}"#
+            ),
+        ),
+        MockResponse::new(200, r#"{"names": ["compiler"]}"#),
+    ]);
+
+    let read = |server| GitHubApiRead::from_client(client(server)).unwrap();
+    let bulk_states = read(&bulk).repos_state("rust-lang", &["rust"]).unwrap();
+    let state = read(&one_by_one)
+        .repo_state("rust-lang", "rust")
+        .unwrap()
+        .unwrap();
+    assert_eq!(state.repo.homepage, None);
+    assert_eq!(bulk_states["rust"], state);
+    // The repo was found in the bulk query despite the different case.
+    assert_eq!(bulk.requests().len(), 2);
+    assert_eq!(one_by_one.requests().len(), 5);
+}
+
+/// A branch protection rule with a page of push and review dismissal allowances, followed by
+/// other pages if `more_allowances` is set.
+fn branch_protection_rule(pattern: &str, pusher: &str, more_allowances: bool) -> String {
+    format!(
+        r#"{{
+            "id": "BPR_{pattern}",
+            "pattern": "{pattern}",
+            "isAdminEnforced": true,
+            "dismissesStaleReviews": false,
+            "requiredStatusChecks": [
+                {{"context": "ci", "app": {{"databaseId": 15368}}}},
+                {{"context": "lint", "app": null}}
+            ],
+            "requiredApprovingReviewCount": 1,
+            "requiresApprovingReviews": true,
+            "requiresLinearHistory": true,
+            "reviewDismissalAllowances": {{
+                "pageInfo": {{"endCursor": "d1", "hasNextPage": {more_allowances}}},
+                "nodes": [{{"actor": {{"login": "{pusher}"}}}}]
+            }},
+            "pushAllowances": {{
+                "pageInfo": {{"endCursor": "a1", "hasNextPage": {more_allowances}}},
+                "nodes": [{{"actor": {{"login": "{pusher}"}}}}]
+            }}
+        }}"#
+    )
+}
+
+#[test]
+fn paginate_branch_protections() {
+    let server = MockServer::start(vec![
+        MockResponse::new(
+            200,
+            &format!(
+                r#"{{"data": {{"repository": {{"branchProtectionRules": {{
+                    "pageInfo": {{"endCursor": "b1", "hasNextPage": true}},
+                    "nodes": [{}]
+                }}}}}}}}"#,
+                branch_protection_rule("master", "bors", true)
+            ),
+        ),
+        MockResponse::new(
+            200,
+            r#"{"data": {"node": {"allowances": {
+                "pageInfo": {"endCursor": "a2", "hasNextPage": false},
+                "nodes": [{"actor": {"organization": {"login": "rust-lang"}, "name": "infra"}}]
+            }}}}"#,
+        ),
+        MockResponse::new(
+            200,
+            r#"{"data": {"node": {"allowances": {
+                "pageInfo": {"endCursor": "d2", "hasNextPage": false},
+                "nodes": [{"actor": {"login": "rust-lang-owner"}}]
+            }}}}"#,
+        ),
+        MockResponse::new(
+            200,
+            &format!(
+                r#"{{"data": {{"repository": {{"branchProtectionRules": {{
+                    "pageInfo": {{"endCursor": "b2", "hasNextPage": false}},
+                    "nodes": [{}]
+                }}}}}}}}"#,
+                branch_protection_rule("beta", "bors", false)
+            ),
+        ),
+    ]);
+    let read = GitHubApiRead::from_client(client(&server)).unwrap();
+    let protections = read.branch_protections("rust-lang", "rust").unwrap();
+
+    assert_eq!(protections.len(), 2);
+    assert_eq!(protections["beta"].1.push_allowances.len(), 1);
+    let (id, master) = &protections["master"];
+    assert_eq!(id, "BPR_master");
+    assert!(master.requires_linear_history);
+    assert_eq!(
+        master.required_status_checks,
+        [
+            RequiredStatusCheck {
+                context: "ci".to_string(),
+                app_id: Some(15368)
+            },
+            RequiredStatusCheck {
+                context: "lint".to_string(),
+                app_id: None
+            }
+        ]
+    );
+    assert!(!master.allows_force_pushes);
+    assert!(matches!(
+        &master.review_dismissal_allowances[..],
+        [PushAllowanceActor::User(first), PushAllowanceActor::User(second)]
+            if first.login == "bors" && second.login == "rust-lang-owner"
+    ));
+    assert!(matches!(
+        &master.push_allowances[..],
+        [PushAllowanceActor::User(user), PushAllowanceActor::Team(team)]
+            if user.login == "bors" && team.name == "infra"
+    ));
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 4);
+    assert!(requests[1].body.contains(r#""id":"BPR_master""#));
+    assert!(requests[1].body.contains("pushAllowances"));
+    assert!(requests[1].body.contains(r#""cursor":"a1""#));
+    assert!(requests[2].body.contains("reviewDismissalAllowances"));
+    assert!(requests[2].body.contains(r#""cursor":"d1""#));
+    assert!(requests[3].body.contains(r#""cursor":"b1""#));
+}
+
+#[test]
+fn create_repo_from_template() {
+    let created = r#"{
+        "node_id": "R_new",
+        "id": 42,
+        "name": "new",
+        "owner": {"login": "rust-lang"},
+        "description": "A new repo",
+        "homepage": null,
+        "archived": false,
+        "default_branch": "main"
+    }"#;
+    let server = MockServer::start(vec![
+        MockResponse::new(201, created),
+        // The repo is generated in the background.
+        MockResponse::new(404, r#"{"message": "Not Found"}"#),
+        MockResponse::new(200, r#"{"name": "main"}"#),
+        MockResponse::new(200, created),
+        MockResponse::new(201, "{}"),
+    ]);
+    let write = GitHubWrite::new(client(&server), false).unwrap();
+    let settings = RepoSettings {
+        description: Some("A new repo".to_string()),
+        homepage: None,
+        archived: false,
+        auto_merge_enabled: true,
+        options: RepoOptions {
+            visibility: Some(RepoVisibility::Internal),
+            has_wiki: Some(false),
+            ..RepoOptions::default()
+        },
+    };
+    let creation = RepoCreationSettings {
+        template: Some("rust-lang/template".to_string()),
+        default_branch: Some("master".to_string()),
+        ..RepoCreationSettings::default()
+    };
+    let repo = write
+        .create_repo("rust-lang", "new", &settings, &creation)
+        .unwrap();
+    assert_eq!(repo.repo_id, 42);
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 5);
+    assert_eq!(requests[0].path, "/repos/rust-lang/template/generate");
+    assert!(requests[0].body.contains(r#""private":true"#));
+    assert_eq!(requests[1].path, "/repos/rust-lang/new/branches/main");
+    assert_eq!(requests[2].path, "/repos/rust-lang/new/branches/main");
+    assert_eq!(requests[3].method, "PATCH");
+    assert!(requests[3].body.contains(r#""visibility":"internal""#));
+    assert!(requests[3].body.contains(r#""has_wiki":false"#));
+    assert!(!requests[3].body.contains("has_issues"));
+    assert_eq!(
+        requests[4].path,
+        "/repos/rust-lang/new/branches/main/rename"
+    );
+    assert_eq!(requests[4].body, r#"{"new_name":"master"}"#);
+}
+
+#[test]
+fn edit_only_changed_repo_settings() {
+    let server = MockServer::start(vec![MockResponse::new(200, "{}")]);
+    let write = GitHubWrite::new(client(&server), false).unwrap();
+    let old = RepoSettings {
+        description: Some("The compiler".to_string()),
+        homepage: Some("https://rust-lang.org".to_string()),
+        archived: false,
+        auto_merge_enabled: false,
+        options: RepoOptions {
+            allow_merge_commit: Some(true),
+            has_wiki: Some(true),
+            has_issues: Some(true),
+            ..RepoOptions::default()
+        },
+    };
+    let new = RepoSettings {
+        description: Some("The compiler".to_string()),
+        homepage: None,
+        archived: false,
+        auto_merge_enabled: true,
+        options: RepoOptions {
+            allow_merge_commit: Some(true),
+            has_wiki: Some(false),
+            has_issues: Some(true),
+            ..RepoOptions::default()
+        },
+    };
+    write.edit_repo("rust-lang", "rust", &old, &new).unwrap();
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].method, "PATCH");
+    assert_eq!(
+        requests[0].body,
+        r#"{"homepage":null,"allow_auto_merge":true,"has_wiki":false}"#
+    );
+}
+
+#[test]
+fn read_repo_rulesets() {
+    let server = MockServer::start(vec![
+        MockResponse::new(
+            200,
+            r#"[{"id": 7, "name": "main", "source_type": "Repository"}]"#,
+        ),
+        MockResponse::new(
+            200,
+            r#"{
+                "id": 7,
+                "name": "main",
+                "target": "branch",
+                "source_type": "Repository",
+                "source": "rust-lang/rust",
+                "enforcement": "active",
+                "bypass_actors": [
+                    {"actor_id": 12, "actor_type": "Team", "bypass_mode": "always"},
+                    {"actor_id": 3, "actor_type": "Integration", "bypass_mode": "pull_request"}
+                ],
+                "conditions": {"ref_name": {"include": ["~DEFAULT_BRANCH"], "exclude": []}},
+                "rules": [
+                    {"type": "update", "parameters": {"update_allows_fetch_and_merge": false}},
+                    {"type": "deletion"},
+                    {"type": "pull_request", "parameters": {
+                        "required_approving_review_count": 1,
+                        "dismiss_stale_reviews_on_push": true,
+                        "require_code_owner_review": false,
+                        "require_last_push_approval": false,
+                        "required_review_thread_resolution": false,
+                        "allowed_merge_methods": ["merge"]
+                    }}
+                ]
+            }"#,
+        ),
+    ]);
+    let read = GitHubApiRead::from_client(client(&server)).unwrap();
+    let rulesets = read.repo_rulesets("rust-lang", "rust").unwrap();
+
+    let (id, main) = &rulesets["main"];
+    assert_eq!(*id, 7);
+    // Rules and bypass actors are sorted to be compared with the expected ones.
+    assert_eq!(
+        main.bypass_actors[0].actor_type,
+        RulesetActorType::Integration
+    );
+    assert!(matches!(
+        &main.rules[..],
+        [RulesetRule::Deletion, RulesetRule::PullRequest(pr), RulesetRule::Update(_)]
+            if pr.required_approving_review_count == 1 && pr.dismiss_stale_reviews_on_push
+    ));
+
+    let requests = server.requests();
+    assert_eq!(
+        requests[0].path,
+        "/repos/rust-lang/rust/rulesets?includes_parents=false"
+    );
+    assert_eq!(requests[1].path, "/repos/rust-lang/rust/rulesets/7");
+}
+
+#[test]
+fn pin_required_status_checks_to_apps() {
+    let server = MockServer::start(vec![MockResponse::new(200, r#"{"data": {}}"#)]);
+    let write = GitHubWrite::new(client(&server), false).unwrap();
+    let protection = BranchProtection {
+        pattern: "master".to_string(),
+        is_admin_enforced: true,
+        dismisses_stale_reviews: false,
+        required_approving_review_count: 1,
+        required_status_checks: vec![
+            RequiredStatusCheck {
+                context: "ci".to_string(),
+                app_id: Some(15368),
+            },
+            RequiredStatusCheck {
+                context: "lint".to_string(),
+                app_id: None,
+            },
+        ],
+        push_allowances: Vec::new(),
+        requires_approving_reviews: true,
+        requires_strict_status_checks: false,
+        requires_code_owner_reviews: false,
+        requires_linear_history: false,
+        requires_conversation_resolution: false,
+        allows_force_pushes: false,
+        allows_deletions: false,
+        review_dismissal_allowances: Vec::new(),
+    };
+    write
+        .upsert_branch_protection(
+            BranchProtectionOp::UpdateBranchProtection("BPR_master".to_string()),
+            "master",
+            &protection,
+        )
+        .unwrap();
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].body.contains(
+        r#""statusChecks":[{"context":"ci","appId":"MDM6QXBwMTUzNjg="},{"context":"lint","appId":"any"}]"#
+    ));
+
+    let plan = serde_json::to_value(&protection).unwrap();
+    assert!(plan.get("required_status_check_contexts").is_none());
+    assert_eq!(
+        plan["required_status_checks"],
+        serde_json::json!([
+            {"context": "ci", "app_id": 15368},
+            {"context": "lint", "app_id": null}
+        ])
+    );
+}
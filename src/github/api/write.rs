@@ -1,13 +1,22 @@
+use anyhow::{bail, Context};
 use log::debug;
 use reqwest::Method;
+use serde::Deserialize;
+use std::time::Duration;
 
 use crate::github::api::{
-    allow_not_found, BranchProtection, BranchProtectionOp, HttpClient, Login, PushAllowanceActor,
-    Repo, RepoPermission, RepoSettings, Team, TeamPrivacy, TeamPushAllowanceActor, TeamRole,
+    allow_not_found, app_node_id, BranchProtection, BranchProtectionOp, HttpClient, Login,
+    ParentTeam, PushAllowanceActor, Repo, RepoCreationSettings, RepoOptions, RepoPermission,
+    RepoSettings, RepoVisibility, Ruleset, Team, TeamPrivacy, TeamPushAllowanceActor, TeamRole,
     UserPushAllowanceActor,
 };
 use crate::utils::ResponseExt;
 
+/// How long to wait for a repo generated from a template to be ready, which GitHub does in the
+/// background.
+const GENERATED_REPO_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const GENERATED_REPO_POLL_ATTEMPTS: u32 = 30;
+
 pub(crate) struct GitHubWrite {
     client: HttpClient,
     dry_run: bool,
@@ -85,12 +94,15 @@ impl GitHubWrite {
         name: &str,
         description: &str,
         privacy: TeamPrivacy,
+        parent: Option<&str>,
     ) -> anyhow::Result<Team> {
         #[derive(serde::Serialize, Debug)]
         struct Req<'a> {
             name: &'a str,
             description: &'a str,
             privacy: TeamPrivacy,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            parent_team_id: Option<u64>,
         }
         debug!("Creating team '{name}' in '{org}'");
         if self.dry_run {
@@ -102,12 +114,18 @@ impl GitHubWrite {
                 description: Some(description.to_string()),
                 privacy,
                 slug: name.to_string(),
+                parent: parent.map(|name| ParentTeam {
+                    name: name.to_string(),
+                }),
             })
         } else {
             let body = &Req {
                 name,
                 description,
                 privacy,
+                parent_team_id: parent
+                    .map(|parent| self.team_database_id(org, parent))
+                    .transpose()?,
             };
             Ok(self
                 .client
@@ -124,6 +142,7 @@ impl GitHubWrite {
         new_name: Option<&str>,
         new_description: Option<&str>,
         new_privacy: Option<TeamPrivacy>,
+        new_parent: Option<Option<&str>>,
     ) -> anyhow::Result<()> {
         #[derive(serde::Serialize, Debug)]
         struct Req<'a> {
@@ -133,14 +152,25 @@ impl GitHubWrite {
             description: Option<&'a str>,
             #[serde(skip_serializing_if = "Option::is_none")]
             privacy: Option<TeamPrivacy>,
+            /// `Some(None)` is serialized as `null`, removing the parent of the team.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            parent_team_id: Option<Option<u64>>,
         }
+        let parent_team_id = match new_parent {
+            Some(Some(parent)) if !self.dry_run => Some(Some(self.team_database_id(org, parent)?)),
+            // The parent might only be "created" by the dry run, without an id to look up.
+            Some(Some(_)) => None,
+            Some(None) => Some(None),
+            None => None,
+        };
         let req = Req {
             name: new_name,
             description: new_description,
             privacy: new_privacy,
+            parent_team_id,
         };
         debug!(
-            "Editing team '{name}' in '{org}' with request: {}",
+            "Editing team '{name}' in '{org}' with request: {} (parent: {new_parent:?})",
             serde_json::to_string(&req).unwrap_or_else(|_| "INVALID_REQUEST".to_string())
         );
         if !self.dry_run {
@@ -151,13 +181,52 @@ impl GitHubWrite {
         Ok(())
     }
 
+    /// Get the id used by the REST API for a team
+    fn team_database_id(&self, org: &str, name: &str) -> anyhow::Result<u64> {
+        let team: Team = self
+            .client
+            .send_option(Method::GET, &format!("orgs/{org}/teams/{name}"))?
+            .with_context(|| format!("team {org}/{name} doesn't exist"))?;
+        team.id
+            .with_context(|| format!("team {org}/{name} doesn't have an id"))
+    }
+
     /// Delete a team by name and org
     pub(crate) fn delete_team(&self, org: &str, slug: &str) -> anyhow::Result<()> {
         debug!("Deleting team with slug '{slug}' in '{org}'");
         if !self.dry_run {
             let method = Method::DELETE;
             let url = &format!("orgs/{org}/teams/{slug}");
-            let resp = self.client.req(method.clone(), url)?.send()?;
+            let resp = self.client.send_empty(method.clone(), url)?;
+            allow_not_found(resp, method, url)?;
+        }
+        Ok(())
+    }
+
+    /// Invite a user to an org as a member, if they're not a member already
+    pub(crate) fn invite_org_member(&self, org: &str, user: &str) -> anyhow::Result<()> {
+        debug!("Inviting '{user}' to org '{org}'");
+        #[derive(serde::Serialize, Debug)]
+        struct Req {
+            role: &'static str,
+        }
+        if !self.dry_run {
+            self.client.send(
+                Method::PUT,
+                &format!("orgs/{org}/memberships/{user}"),
+                &Req { role: "member" },
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Remove a user from an org, and from all of its teams
+    pub(crate) fn remove_org_member(&self, org: &str, user: &str) -> anyhow::Result<()> {
+        debug!("Removing '{user}' from org '{org}'");
+        if !self.dry_run {
+            let url = &format!("orgs/{org}/memberships/{user}");
+            let method = Method::DELETE;
+            let resp = self.client.send_empty(method.clone(), url)?;
             allow_not_found(resp, method, url)?;
         }
         Ok(())
@@ -198,7 +267,7 @@ impl GitHubWrite {
         if !self.dry_run {
             let url = &format!("orgs/{org}/teams/{team}/memberships/{user}");
             let method = Method::DELETE;
-            let resp = self.client.req(method.clone(), url)?.send()?;
+            let resp = self.client.send_empty(method.clone(), url)?;
             allow_not_found(resp, method, url)?;
         }
 
@@ -211,6 +280,7 @@ impl GitHubWrite {
         org: &str,
         name: &str,
         settings: &RepoSettings,
+        creation: &RepoCreationSettings,
     ) -> anyhow::Result<Repo> {
         #[derive(serde::Serialize, Debug)]
         struct Req<'a> {
@@ -219,15 +289,34 @@ impl GitHubWrite {
             homepage: &'a Option<&'a str>,
             auto_init: bool,
             allow_auto_merge: bool,
+            #[serde(flatten)]
+            options: &'a RepoOptions,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            license_template: Option<&'a str>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            gitignore_template: Option<&'a str>,
         }
-        let req = &Req {
-            name,
-            description: settings.description.as_deref().unwrap_or_default(),
-            homepage: &settings.homepage.as_deref(),
-            auto_init: true,
-            allow_auto_merge: settings.auto_merge_enabled,
-        };
-        debug!("Creating the repo {org}/{name} with {req:?}");
+        #[derive(serde::Serialize, Debug)]
+        struct TemplateReq<'a> {
+            owner: &'a str,
+            name: &'a str,
+            description: &'a str,
+            private: bool,
+        }
+        #[derive(serde::Serialize, Debug)]
+        struct TemplateEditReq<'a> {
+            homepage: &'a Option<&'a str>,
+            allow_auto_merge: bool,
+            #[serde(flatten)]
+            options: &'a RepoOptions,
+        }
+        #[derive(serde::Deserialize)]
+        struct DefaultBranch {
+            default_branch: String,
+        }
+        let description = settings.description.as_deref().unwrap_or_default();
+        let homepage = &settings.homepage.as_deref();
+        debug!("Creating the repo {org}/{name} with {creation:?}");
         if self.dry_run {
             Ok(Repo {
                 node_id: String::from("ID"),
@@ -238,33 +327,121 @@ impl GitHubWrite {
                 homepage: settings.homepage.clone(),
                 archived: false,
                 allow_auto_merge: Some(settings.auto_merge_enabled),
+                options: settings.options.clone(),
             })
         } else {
-            Ok(self
+            let created: serde_json::Value = if let Some(template) = &creation.template {
+                // Repos generated from a template can only be public or private when they're
+                // created, the rest of the settings has to be applied afterwards.
+                let req = TemplateReq {
+                    owner: org,
+                    name,
+                    description,
+                    private: settings
+                        .options
+                        .visibility
+                        .map_or(false, |v| v != RepoVisibility::Public),
+                };
+                let created = self
+                    .client
+                    .send(Method::POST, &format!("repos/{template}/generate"), &req)?
+                    .json_annotated()?;
+                let branch = DefaultBranch::deserialize(&created)?.default_branch;
+                self.wait_for_generated_repo(org, name, &branch)?;
+                let req = TemplateEditReq {
+                    homepage,
+                    allow_auto_merge: settings.auto_merge_enabled,
+                    options: &settings.options,
+                };
+                self.client
+                    .send(Method::PATCH, &format!("repos/{org}/{name}"), &req)?;
+                created
+            } else {
+                let req = Req {
+                    name,
+                    description,
+                    homepage,
+                    auto_init: true,
+                    allow_auto_merge: settings.auto_merge_enabled,
+                    options: &settings.options,
+                    license_template: creation.license_template.as_deref(),
+                    gitignore_template: creation.gitignore_template.as_deref(),
+                };
+                self.client
+                    .send(Method::POST, &format!("orgs/{org}/repos"), &req)?
+                    .json_annotated()?
+            };
+            if let Some(default_branch) = &creation.default_branch {
+                let current = DefaultBranch::deserialize(&created)?.default_branch;
+                if *default_branch != current {
+                    self.rename_branch(org, name, &current, default_branch)?;
+                }
+            }
+            Ok(Repo::deserialize(created)?)
+        }
+    }
+
+    /// Wait for the default branch of a repo generated from a template to exist: the repo is
+    /// populated in the background, and editing it before then fails with a 404.
+    fn wait_for_generated_repo(&self, org: &str, repo: &str, branch: &str) -> anyhow::Result<()> {
+        let url = format!("repos/{org}/{repo}/branches/{branch}");
+        for _ in 0..GENERATED_REPO_POLL_ATTEMPTS {
+            let branch = self
                 .client
-                .send(Method::POST, &format!("orgs/{org}/repos"), req)?
-                .json_annotated()?)
+                .send_option::<serde_json::Value>(Method::GET, &url)?;
+            if branch.is_some() {
+                return Ok(());
+            }
+            debug!("Waiting for {org}/{repo} to be generated from its template");
+            std::thread::sleep(GENERATED_REPO_POLL_INTERVAL);
         }
+        bail!("repo {org}/{repo} wasn't generated from its template in time")
     }
 
+    /// Rename a branch of a repo
+    fn rename_branch(&self, org: &str, repo: &str, from: &str, to: &str) -> anyhow::Result<()> {
+        #[derive(serde::Serialize, Debug)]
+        struct Req<'a> {
+            new_name: &'a str,
+        }
+        debug!("Renaming branch {from} of {org}/{repo} to {to}");
+        self.client.send(
+            Method::POST,
+            &format!("repos/{org}/{repo}/branches/{from}/rename"),
+            &Req { new_name: to },
+        )?;
+        Ok(())
+    }
+
+    /// Edit the settings of a repo, only sending the ones that differ from the current settings so
+    /// that the settings not managed by sync-team are left untouched.
     pub(crate) fn edit_repo(
         &self,
         org: &str,
         repo_name: &str,
-        settings: &RepoSettings,
+        old: &RepoSettings,
+        new: &RepoSettings,
     ) -> anyhow::Result<()> {
         #[derive(serde::Serialize, Debug)]
         struct Req<'a> {
-            description: &'a Option<&'a str>,
-            homepage: &'a Option<&'a str>,
-            archived: bool,
-            allow_auto_merge: bool,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            description: Option<Option<&'a str>>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            homepage: Option<Option<&'a str>>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            archived: Option<bool>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            allow_auto_merge: Option<bool>,
+            #[serde(flatten)]
+            options: RepoOptions,
         }
         let req = Req {
-            description: &settings.description.as_deref(),
-            homepage: &settings.homepage.as_deref(),
-            archived: settings.archived,
-            allow_auto_merge: settings.auto_merge_enabled,
+            description: (old.description != new.description).then_some(new.description.as_deref()),
+            homepage: (old.homepage != new.homepage).then_some(new.homepage.as_deref()),
+            archived: (old.archived != new.archived).then_some(new.archived),
+            allow_auto_merge: (old.auto_merge_enabled != new.auto_merge_enabled)
+                .then_some(new.auto_merge_enabled),
+            options: new.options.changed_from(&old.options),
         };
         debug!("Editing repo {}/{} with {:?}", org, repo_name, req);
         if !self.dry_run {
@@ -274,6 +451,76 @@ impl GitHubWrite {
         Ok(())
     }
 
+    /// Create a ruleset in a repo
+    pub(crate) fn create_ruleset(
+        &self,
+        org: &str,
+        repo: &str,
+        ruleset: &Ruleset,
+    ) -> anyhow::Result<()> {
+        debug!("Creating ruleset '{}' in {org}/{repo}", ruleset.name);
+        if !self.dry_run {
+            self.client.send(
+                Method::POST,
+                &format!("repos/{org}/{repo}/rulesets"),
+                ruleset,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Replace the settings and rules of a ruleset
+    pub(crate) fn update_ruleset(
+        &self,
+        org: &str,
+        repo: &str,
+        id: u64,
+        ruleset: &Ruleset,
+    ) -> anyhow::Result<()> {
+        debug!("Updating ruleset '{}' in {org}/{repo}", ruleset.name);
+        if !self.dry_run {
+            self.client.send(
+                Method::PUT,
+                &format!("repos/{org}/{repo}/rulesets/{id}"),
+                ruleset,
+            )?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn delete_ruleset(&self, org: &str, repo: &str, id: u64) -> anyhow::Result<()> {
+        debug!("Deleting ruleset {id} in {org}/{repo}");
+        if !self.dry_run {
+            let url = &format!("repos/{org}/{repo}/rulesets/{id}");
+            let method = Method::DELETE;
+            let resp = self.client.send_empty(method.clone(), url)?;
+            allow_not_found(resp, method, url)?;
+        }
+        Ok(())
+    }
+
+    /// Replace all the topics of a repo
+    pub(crate) fn set_repo_topics(
+        &self,
+        org: &str,
+        repo: &str,
+        topics: &[String],
+    ) -> anyhow::Result<()> {
+        #[derive(serde::Serialize, Debug)]
+        struct Req<'a> {
+            names: &'a [String],
+        }
+        debug!("Setting the topics of {org}/{repo} to {topics:?}");
+        if !self.dry_run {
+            self.client.send(
+                Method::PUT,
+                &format!("repos/{org}/{repo}/topics"),
+                &Req { names: topics },
+            )?;
+        }
+        Ok(())
+    }
+
     pub(crate) fn add_repo_to_app_installation(
         &self,
         installation_id: u64,
@@ -282,11 +529,10 @@ impl GitHubWrite {
         debug!("Adding repository {repository_id} to installation {installation_id}");
         if !self.dry_run {
             self.client
-                .req(
+                .send_empty(
                     Method::PUT,
                     &format!("user/installations/{installation_id}/repositories/{repository_id}"),
                 )?
-                .send()?
                 .custom_error_for_status()?;
         }
         Ok(())
@@ -300,11 +546,10 @@ impl GitHubWrite {
         debug!("Removing repository {repository_id} from installation {installation_id}");
         if !self.dry_run {
             self.client
-                .req(
+                .send_empty(
                     Method::DELETE,
                     &format!("user/installations/{installation_id}/repositories/{repository_id}"),
                 )?
-                .send()?
                 .custom_error_for_status()?;
         }
         Ok(())
@@ -368,7 +613,7 @@ impl GitHubWrite {
         if !self.dry_run {
             let method = Method::DELETE;
             let url = &format!("orgs/{org}/teams/{team}/repos/{org}/{repo}");
-            let resp = self.client.req(method.clone(), url)?.send()?;
+            let resp = self.client.send_empty(method.clone(), url)?;
             allow_not_found(resp, method, url)?;
         }
 
@@ -386,7 +631,7 @@ impl GitHubWrite {
         if !self.dry_run {
             let method = Method::DELETE;
             let url = &format!("repos/{org}/{repo}/collaborators/{collaborator}");
-            let resp = self.client.req(method.clone(), url)?.send()?;
+            let resp = self.client.send_empty(method.clone(), url)?;
             allow_not_found(resp, method, url)?;
         }
         Ok(())
@@ -405,13 +650,29 @@ impl GitHubWrite {
         struct Params<'a> {
             id: &'a str,
             pattern: &'a str,
-            contexts: &'a [String],
+            status_checks: Vec<StatusCheck<'a>>,
+            requires_status_checks: bool,
+            requires_strict_status_checks: bool,
+            is_admin_enforced: bool,
             dismiss_stale: bool,
             review_count: u8,
             restricts_pushes: bool,
             // Is a PR required to push into this branch?
             requires_approving_reviews: bool,
+            requires_code_owner_reviews: bool,
+            requires_linear_history: bool,
+            requires_conversation_resolution: bool,
+            allows_force_pushes: bool,
+            allows_deletions: bool,
             push_actor_ids: &'a [String],
+            restricts_review_dismissals: bool,
+            review_dismissal_actor_ids: &'a [String],
+        }
+        #[derive(Debug, serde::Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct StatusCheck<'a> {
+            context: &'a str,
+            app_id: String,
         }
         let mutation_name = match op {
             BranchProtectionOp::CreateForRepo(_) => "createBranchProtectionRule",
@@ -426,18 +687,26 @@ impl GitHubWrite {
             BranchProtectionOp::UpdateBranchProtection(id) => id,
         };
         let query = format!("
-        mutation($id: ID!, $pattern:String!, $contexts: [String!], $dismissStale: Boolean, $reviewCount: Int, $pushActorIds: [ID!], $restrictsPushes: Boolean, $requiresApprovingReviews: Boolean) {{
+        mutation($id: ID!, $pattern:String!, $statusChecks: [RequiredStatusCheckInput!], $requiresStatusChecks: Boolean, $requiresStrictStatusChecks: Boolean, $isAdminEnforced: Boolean, $dismissStale: Boolean, $reviewCount: Int, $pushActorIds: [ID!], $restrictsPushes: Boolean, $requiresApprovingReviews: Boolean, $requiresCodeOwnerReviews: Boolean, $requiresLinearHistory: Boolean, $requiresConversationResolution: Boolean, $allowsForcePushes: Boolean, $allowsDeletions: Boolean, $restrictsReviewDismissals: Boolean, $reviewDismissalActorIds: [ID!]) {{
             {mutation_name}(input: {{
                 {id_field}: $id, 
                 pattern: $pattern, 
-                requiresStatusChecks: true, 
-                requiredStatusCheckContexts: $contexts, 
-                isAdminEnforced: true, 
+                requiresStatusChecks: $requiresStatusChecks, 
+                requiresStrictStatusChecks: $requiresStrictStatusChecks,
+                requiredStatusChecks: $statusChecks, 
+                isAdminEnforced: $isAdminEnforced, 
                 requiredApprovingReviewCount: $reviewCount, 
                 dismissesStaleReviews: $dismissStale, 
                 requiresApprovingReviews: $requiresApprovingReviews,
+                requiresCodeOwnerReviews: $requiresCodeOwnerReviews,
+                requiresLinearHistory: $requiresLinearHistory,
+                requiresConversationResolution: $requiresConversationResolution,
+                allowsForcePushes: $allowsForcePushes,
+                allowsDeletions: $allowsDeletions,
                 restrictsPushes: $restrictsPushes,
-                pushActorIds: $pushActorIds
+                pushActorIds: $pushActorIds,
+                restrictsReviewDismissals: $restrictsReviewDismissals,
+                reviewDismissalActorIds: $reviewDismissalActorIds
             }}) {{
               branchProtectionRule {{
                 id
@@ -445,18 +714,9 @@ impl GitHubWrite {
             }}
           }}
         ");
-        let mut push_actor_ids = vec![];
-        for actor in &branch_protection.push_allowances {
-            match actor {
-                PushAllowanceActor::User(UserPushAllowanceActor { login: name }) => {
-                    push_actor_ids.push(self.user_id(name)?);
-                }
-                PushAllowanceActor::Team(TeamPushAllowanceActor {
-                    organization: Login { login: org },
-                    name,
-                }) => push_actor_ids.push(self.team_id(org, name)?),
-            }
-        }
+        let push_actor_ids = self.actor_ids(&branch_protection.push_allowances)?;
+        let review_dismissal_actor_ids =
+            self.actor_ids(&branch_protection.review_dismissal_allowances)?;
 
         if !self.dry_run {
             let _: serde_json::Value = self.client.graphql(
@@ -464,7 +724,18 @@ impl GitHubWrite {
                 Params {
                     id,
                     pattern,
-                    contexts: &branch_protection.required_status_check_contexts,
+                    status_checks: branch_protection
+                        .required_status_checks
+                        .iter()
+                        .map(|check| StatusCheck {
+                            context: &check.context,
+                            // Otherwise GitHub pins the check to the app that last reported it.
+                            app_id: check.app_id.map_or_else(|| "any".to_string(), app_node_id),
+                        })
+                        .collect(),
+                    requires_status_checks: !branch_protection.required_status_checks.is_empty(),
+                    requires_strict_status_checks: branch_protection.requires_strict_status_checks,
+                    is_admin_enforced: branch_protection.is_admin_enforced,
                     dismiss_stale: branch_protection.dismisses_stale_reviews,
                     review_count: branch_protection.required_approving_review_count,
                     // We restrict merges, if we have explicitly set some actors to be
@@ -473,12 +744,37 @@ impl GitHubWrite {
                     restricts_pushes: !push_actor_ids.is_empty(),
                     push_actor_ids: &push_actor_ids,
                     requires_approving_reviews: branch_protection.requires_approving_reviews,
+                    requires_code_owner_reviews: branch_protection.requires_code_owner_reviews,
+                    requires_linear_history: branch_protection.requires_linear_history,
+                    requires_conversation_resolution: branch_protection
+                        .requires_conversation_resolution,
+                    allows_force_pushes: branch_protection.allows_force_pushes,
+                    allows_deletions: branch_protection.allows_deletions,
+                    restricts_review_dismissals: !review_dismissal_actor_ids.is_empty(),
+                    review_dismissal_actor_ids: &review_dismissal_actor_ids,
                 },
             )?;
         }
         Ok(())
     }
 
+    /// Resolve the node IDs of users and teams
+    fn actor_ids(&self, actors: &[PushAllowanceActor]) -> anyhow::Result<Vec<String>> {
+        let mut ids = Vec::new();
+        for actor in actors {
+            match actor {
+                PushAllowanceActor::User(UserPushAllowanceActor { login: name }) => {
+                    ids.push(self.user_id(name)?);
+                }
+                PushAllowanceActor::Team(TeamPushAllowanceActor {
+                    organization: Login { login: org },
+                    name,
+                }) => ids.push(self.team_id(org, name)?),
+            }
+        }
+        Ok(ids)
+    }
+
     /// Delete a branch protection
     pub(crate) fn delete_branch_protection(
         &self,
@@ -2,62 +2,117 @@ mod api;
 #[cfg(test)]
 mod tests;
 
-use self::api::{BranchProtectionOp, TeamPrivacy, TeamRole};
-use crate::github::api::{GithubRead, Login, PushAllowanceActor, RepoPermission, RepoSettings};
+use self::api::{BranchProtectionOp, TeamRole};
+use crate::config::{
+    BotConfig, BranchProtectionConfig, Config, GitHubConfig, OrgConfig, RepoConfig,
+};
+use crate::failures::Failures;
+use crate::github::api::{GithubRead, Login, PushAllowanceActor, RepoSettings};
+use crate::safety::Removals;
+use crate::service::SyncService;
+use crate::team_api::TeamApi;
+use crate::utils::parallel_map;
+use anyhow::{bail, Context};
 use log::debug;
 use rust_team_data::v1::{Bot, BranchProtectionMode};
-use std::collections::{HashMap, HashSet};
-use std::fmt::{Display, Formatter, Write};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
+use std::sync::Mutex;
 
-pub(crate) use self::api::{GitHubApiRead, GitHubWrite, HttpClient};
+pub(crate) use self::api::{
+    GitHubApiRead, GitHubWrite, HttpClient, RepoCreationSettings, RepoOptions, RepoPermission,
+    RepoVisibility, Ruleset, TeamPrivacy,
+};
 
 static DEFAULT_DESCRIPTION: &str = "Managed by the rust-lang/team repository.";
 static DEFAULT_PRIVACY: TeamPrivacy = TeamPrivacy::Closed;
+static DEFAULT_ORG_CONFIG: OrgConfig = OrgConfig {
+    delete_unmanaged_teams: false,
+    ignored_teams: Vec::new(),
+    team_permissions: BTreeMap::new(),
+    ignored_collaborators: Vec::new(),
+    report_unmanaged_repos: false,
+    known_unmanaged_repos: Vec::new(),
+    fail_on_new_unmanaged_repos: false,
+};
 
 pub(crate) fn create_diff(
     github: Box<dyn GithubRead>,
     teams: Vec<rust_team_data::v1::Team>,
     repos: Vec<rust_team_data::v1::Repo>,
+    config: GitHubConfig,
 ) -> anyhow::Result<Diff> {
-    let github = SyncGitHub::new(github, teams, repos)?;
+    let github = SyncGitHub::new(github, teams, repos, config)?;
     github.diff_all()
 }
 
-type OrgName = String;
-type RepoName = String;
-
-#[derive(Copy, Clone, Debug, PartialEq)]
-enum GithubApp {
-    RenovateBot,
+/// Synchronization of the GitHub teams and repositories defined in the team repo.
+pub(crate) struct GitHubSync {
+    client: HttpClient,
+    teams: Vec<rust_team_data::v1::Team>,
+    repos: Vec<rust_team_data::v1::Repo>,
+    config: GitHubConfig,
+    dry_run: bool,
 }
 
-impl GithubApp {
-    fn from_id(app_id: u64) -> Option<Self> {
-        match app_id {
-            2740 => Some(GithubApp::RenovateBot),
-            _ => None,
-        }
+impl SyncService for GitHubSync {
+    type Diff = Diff;
+
+    fn load(team_api: &TeamApi, config: &Config, dry_run: bool) -> anyhow::Result<Self> {
+        let token = crate::get_env("GITHUB_TOKEN")?;
+        Ok(Self {
+            client: HttpClient::from_url_and_token(
+                "https://api.github.com/".to_string(),
+                token,
+                config.retry,
+            )?,
+            teams: team_api.get_teams()?,
+            repos: team_api.get_repos()?,
+            config: config.github.clone(),
+            dry_run,
+        })
     }
-}
 
-impl Display for GithubApp {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match self {
-            GithubApp::RenovateBot => f.write_str("RenovateBot"),
-        }
+    fn diff(&self) -> anyhow::Result<Diff> {
+        let gh_read = Box::new(GitHubApiRead::from_client(self.client.clone())?);
+        create_diff(
+            gh_read,
+            self.teams.clone(),
+            self.repos.clone(),
+            self.config.clone(),
+        )
+    }
+
+    fn removals(&self, diff: &Diff) -> Removals {
+        diff.removals()
+    }
+
+    fn check(&self, diff: &Diff) -> anyhow::Result<()> {
+        diff.check()
+    }
+
+    fn apply(&self, diff: Diff, failures: &mut Failures) -> anyhow::Result<()> {
+        diff.apply(
+            &GitHubWrite::new(self.client.clone(), self.dry_run)?,
+            failures,
+        )
     }
 }
 
+type OrgName = String;
+type RepoName = String;
+
 #[derive(Clone, Debug)]
 struct OrgAppInstallation {
-    app: GithubApp,
+    /// Name of the app in the `github.apps` registry.
+    app: String,
     installation_id: u64,
     repositories: HashSet<RepoName>,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
 struct AppInstallation {
-    app: GithubApp,
+    app: String,
     installation_id: u64,
 }
 
@@ -68,6 +123,7 @@ struct SyncGitHub {
     usernames_cache: HashMap<u64, String>,
     org_owners: HashMap<OrgName, HashSet<u64>>,
     org_apps: HashMap<OrgName, Vec<OrgAppInstallation>>,
+    config: GitHubConfig,
 }
 
 impl SyncGitHub {
@@ -75,6 +131,7 @@ impl SyncGitHub {
         github: Box<dyn GithubRead>,
         teams: Vec<rust_team_data::v1::Team>,
         repos: Vec<rust_team_data::v1::Repo>,
+        config: GitHubConfig,
     ) -> anyhow::Result<Self> {
         debug!("caching mapping between user ids and usernames");
         let users = teams
@@ -105,7 +162,13 @@ impl SyncGitHub {
             let mut installations: Vec<OrgAppInstallation> = vec![];
 
             for installation in github.org_app_installations(org)? {
-                if let Some(app) = GithubApp::from_id(installation.app_id) {
+                // Only load installations of the apps we know about, to avoid removing unknown
+                // installations.
+                let app = config
+                    .apps
+                    .iter()
+                    .find(|app| app.app_id == installation.app_id);
+                if let Some(app) = app {
                     let mut repositories = HashSet::new();
                     for repo_installation in
                         github.app_installation_repos(installation.installation_id)?
@@ -113,7 +176,7 @@ impl SyncGitHub {
                         repositories.insert(repo_installation.name);
                     }
                     installations.push(OrgAppInstallation {
-                        app,
+                        app: app.name.clone(),
                         installation_id: installation.installation_id,
                         repositories,
                     });
@@ -129,67 +192,221 @@ impl SyncGitHub {
             usernames_cache,
             org_owners,
             org_apps,
+            config,
         })
     }
 
     pub(crate) fn diff_all(&self) -> anyhow::Result<Diff> {
+        let org_membership_diffs = self.diff_org_memberships()?;
         let team_diffs = self.diff_teams()?;
         let repo_diffs = self.diff_repos()?;
+        let unmanaged_repos = self.unmanaged_repos()?;
 
         Ok(Diff {
+            org_membership_diffs,
             team_diffs,
             repo_diffs,
+            unmanaged_repos,
         })
     }
 
+    /// List the repos of the orgs opted into the report that are not in the team repo.
+    fn unmanaged_repos(&self) -> anyhow::Result<Vec<UnmanagedRepo>> {
+        let mut orgs = self
+            .config
+            .orgs
+            .iter()
+            .filter(|(_, config)| config.report_unmanaged_repos)
+            .collect::<Vec<_>>();
+        orgs.sort_by_key(|(org, _)| *org);
+
+        let mut unmanaged = Vec::new();
+        for (org, config) in orgs {
+            let mut repos = self
+                .github
+                .org_repositories(org)?
+                .into_iter()
+                .filter(|repo| {
+                    !self.repos.iter().any(|managed| {
+                        &managed.org == org && managed.name.eq_ignore_ascii_case(&repo.name)
+                    })
+                })
+                .collect::<Vec<_>>();
+            repos.sort_by(|a, b| a.name.cmp(&b.name));
+
+            let collaborators = parallel_map(&repos, self.config.read_concurrency, |repo| {
+                self.github.repo_collaborators(org, &repo.name)
+            });
+            for (repo, collaborators) in repos.into_iter().zip(collaborators) {
+                let mut admins = collaborators?
+                    .into_iter()
+                    .filter(|c| c.permission == RepoPermission::Admin)
+                    .map(|c| c.name)
+                    .collect::<Vec<_>>();
+                admins.sort();
+                let new = !config
+                    .known_unmanaged_repos
+                    .iter()
+                    .any(|known| known.eq_ignore_ascii_case(&repo.name));
+                unmanaged.push(UnmanagedRepo {
+                    org: org.clone(),
+                    name: repo.name,
+                    visibility: repo.options.visibility,
+                    archived: repo.archived,
+                    admins,
+                    new,
+                    fail: new && config.fail_on_new_unmanaged_repos,
+                });
+            }
+        }
+        Ok(unmanaged)
+    }
+
     fn diff_teams(&self) -> anyhow::Result<Vec<TeamDiff>> {
-        let mut diffs = Vec::new();
-        let mut unseen_github_teams = HashMap::new();
-        for team in &self.teams {
-            if let Some(gh) = &team.github {
-                for github_team in &gh.teams {
-                    // Get existing teams we haven't seen yet
-                    let unseen_github_teams = match unseen_github_teams.get_mut(&github_team.org) {
-                        Some(ts) => ts,
-                        None => {
-                            let ts: HashMap<_, _> = self
-                                .github
-                                .org_teams(&github_team.org)?
-                                .into_iter()
-                                .collect();
-                            unseen_github_teams
-                                .entry(github_team.org.clone())
-                                .or_insert(ts)
-                        }
-                    };
-                    // Remove the current team from the collection of unseen GitHub teams
-                    unseen_github_teams.remove(&github_team.name);
+        let github_teams = self
+            .teams
+            .iter()
+            .filter_map(|team| Some((team, team.github.as_ref()?)))
+            .flat_map(|(team, gh)| gh.teams.iter().map(move |github_team| (team, github_team)))
+            .collect::<Vec<_>>();
 
-                    diffs.push(self.diff_team(github_team)?);
+        let mut unseen_github_teams = HashMap::new();
+        for (_, github_team) in &github_teams {
+            // Get existing teams we haven't seen yet
+            let unseen_github_teams = match unseen_github_teams.get_mut(&github_team.org) {
+                Some(ts) => ts,
+                None => {
+                    let ts: HashMap<_, _> = self
+                        .github
+                        .org_teams(&github_team.org)?
+                        .into_iter()
+                        .collect();
+                    unseen_github_teams
+                        .entry(github_team.org.clone())
+                        .or_insert(ts)
                 }
-            }
-        }
+            };
+            // Remove the current team from the collection of unseen GitHub teams
+            unseen_github_teams.remove(&github_team.name);
+        }
+
+        let mut diffs = parallel_map(
+            &github_teams,
+            self.config.read_concurrency,
+            |(team, github_team)| self.diff_team(team, github_team),
+        )
+        .into_iter()
+        .collect::<anyhow::Result<Vec<_>>>()?;
+        // Parent teams have to exist before their children can be nested in them.
+        diffs.sort_by_cached_key(|diff| match diff {
+            TeamDiff::Create(c) => self.nesting_depth(&c.org, &c.name),
+            TeamDiff::Edit(e) => self.nesting_depth(&e.org, &e.name),
+            TeamDiff::Delete(_) => 0,
+        });
 
-        let delete_diffs = unseen_github_teams
+        let mut delete_diffs = unseen_github_teams
             .into_iter()
-            .filter(|(org, _)| matches!(org.as_str(), "rust-lang" | "rust-lang-nursery")) // Only delete unmanaged teams in `rust-lang` and `rust-lang-nursery` for now
+            .filter(|(org, _)| self.org_config(org).delete_unmanaged_teams)
             .flat_map(|(org, remaining_github_teams)| {
                 remaining_github_teams
                     .into_iter()
                     .map(move |t| (org.clone(), t))
             })
-            // Don't delete the special bot teams
-            .filter(|(_, (remaining_github_team, _))| {
-                !BOTS_TEAMS.contains(&remaining_github_team.as_str())
+            // Don't delete the special bot teams, nor the teams the org ignores
+            .filter(|(org, (remaining_github_team, _))| {
+                !self.config.bot_teams.contains(remaining_github_team)
+                    && !self
+                        .org_config(org)
+                        .ignored_teams
+                        .contains(remaining_github_team)
             })
-            .map(|(org, (name, slug))| TeamDiff::Delete(DeleteTeamDiff { org, name, slug }));
+            .collect::<Vec<_>>();
+        // Keep the diff stable across runs, so that saved plans can be compared.
+        delete_diffs.sort();
+
+        diffs.extend(
+            delete_diffs
+                .into_iter()
+                .map(|(org, (name, slug))| TeamDiff::Delete(DeleteTeamDiff { org, name, slug })),
+        );
+
+        Ok(diffs)
+    }
 
-        diffs.extend(delete_diffs);
+    /// Compare the members of the orgs opted into membership synchronization with the members of
+    /// their teams. Org owners and allowed members are never removed.
+    fn diff_org_memberships(&self) -> anyhow::Result<Vec<OrgMembershipDiff>> {
+        let mut orgs = self.config.org_membership.iter().collect::<Vec<_>>();
+        orgs.sort_by_key(|(org, _)| *org);
 
+        let mut diffs = Vec::new();
+        for (org, config) in orgs {
+            let expected_members = self
+                .teams
+                .iter()
+                .filter_map(|team| team.github.as_ref())
+                .flat_map(|gh| &gh.teams)
+                .filter(|gh_team| &gh_team.org == org)
+                .flat_map(|gh_team| &gh_team.members)
+                .copied()
+                .collect::<BTreeSet<_>>();
+            let owners = match self.org_owners.get(org) {
+                Some(owners) => owners.clone(),
+                None => self.github.org_owners(org)?,
+            };
+            let members = self.github.org_members(org)?;
+            let invitations = self
+                .github
+                .org_invitations(org)?
+                .into_iter()
+                .map(|login| login.to_lowercase())
+                .collect::<HashSet<_>>();
+
+            let mut diff = |member: &str, operation| {
+                diffs.push(OrgMembershipDiff {
+                    org: org.clone(),
+                    member: member.to_string(),
+                    operation,
+                })
+            };
+            for user in &expected_members {
+                let login = self.username(*user)?;
+                if !members.contains_key(user) && !invitations.contains(&login.to_lowercase()) {
+                    diff(login, OrgMembershipDiffOperation::Invite);
+                }
+            }
+
+            let mut unmanaged = members
+                .into_iter()
+                .filter(|(user, login)| {
+                    !expected_members.contains(user)
+                        && !owners.contains(user)
+                        && !config
+                            .allowed_members
+                            .iter()
+                            .any(|allowed| allowed.eq_ignore_ascii_case(login))
+                })
+                .map(|(_, login)| login)
+                .collect::<Vec<_>>();
+            unmanaged.sort();
+            for login in unmanaged {
+                if config.remove_unmanaged {
+                    diff(&login, OrgMembershipDiffOperation::Remove);
+                } else {
+                    diff(&login, OrgMembershipDiffOperation::Unmanaged);
+                }
+            }
+        }
         Ok(diffs)
     }
 
-    fn diff_team(&self, github_team: &rust_team_data::v1::GitHubTeam) -> anyhow::Result<TeamDiff> {
+    fn diff_team(
+        &self,
+        team: &rust_team_data::v1::Team,
+        github_team: &rust_team_data::v1::GitHubTeam,
+    ) -> anyhow::Result<TeamDiff> {
+        let (expected_description, expected_privacy) = team_settings(team);
+
         // Ensure the team exists and is consistent
         let team = match self.github.team(&github_team.org, &github_team.name)? {
             Some(team) => team,
@@ -199,14 +416,17 @@ impl SyncGitHub {
                     .iter()
                     .map(|member| {
                         let expected_role = self.expected_role(&github_team.org, *member);
-                        (self.usernames_cache[member].clone(), expected_role)
+                        Ok((self.username(*member)?.clone(), expected_role))
                     })
-                    .collect();
+                    .collect::<anyhow::Result<_>>()?;
                 return Ok(TeamDiff::Create(CreateTeamDiff {
                     org: github_team.org.clone(),
                     name: github_team.name.clone(),
-                    description: DEFAULT_DESCRIPTION.to_owned(),
-                    privacy: DEFAULT_PRIVACY,
+                    description: expected_description.to_owned(),
+                    privacy: expected_privacy,
+                    parent: self
+                        .expected_parent(&github_team.org, &github_team.name)
+                        .map(String::from),
                     members,
                 }));
             }
@@ -218,17 +438,29 @@ impl SyncGitHub {
         let mut description_diff = None;
         match &team.description {
             Some(description) => {
-                if description != DEFAULT_DESCRIPTION {
-                    description_diff = Some((description.clone(), DEFAULT_DESCRIPTION.to_owned()));
+                if description != expected_description {
+                    description_diff = Some((description.clone(), expected_description.to_owned()));
                 }
             }
             None => {
-                description_diff = Some((String::new(), DEFAULT_DESCRIPTION.to_owned()));
+                description_diff = Some((String::new(), expected_description.to_owned()));
             }
         }
         let mut privacy_diff = None;
-        if team.privacy != DEFAULT_PRIVACY {
-            privacy_diff = Some((team.privacy, DEFAULT_PRIVACY))
+        if team.privacy != expected_privacy {
+            privacy_diff = Some((team.privacy, expected_privacy))
+        }
+        // Teams without a parent in the team repo keep the parent they have on GitHub, which
+        // might have been set by hand.
+        let mut parent_diff = None;
+        let current_parent = team.parent.as_ref().map(|p| p.name.as_str());
+        if let Some(expected_parent) = self.expected_parent(&github_team.org, &github_team.name) {
+            if current_parent != Some(expected_parent) {
+                parent_diff = Some((
+                    current_parent.map(String::from),
+                    expected_parent.to_string(),
+                ));
+            }
         }
 
         let mut member_diffs = Vec::new();
@@ -241,7 +473,7 @@ impl SyncGitHub {
         // Ensure all expected members are in the team
         for member in &github_team.members {
             let expected_role = self.expected_role(&github_team.org, *member);
-            let username = &self.usernames_cache[member];
+            let username = self.username(*member)?;
             if let Some(member) = current_members.remove(member) {
                 if member.role != expected_role {
                     member_diffs.push((
@@ -263,8 +495,13 @@ impl SyncGitHub {
 
         // The previous cycle removed expected members from current_members, so it only contains
         // members to delete now.
-        for member in current_members.values() {
-            member_diffs.push((member.username.clone(), MemberDiff::Delete));
+        let mut removed_members = current_members
+            .into_values()
+            .map(|member| member.username)
+            .collect::<Vec<_>>();
+        removed_members.sort();
+        for member in removed_members {
+            member_diffs.push((member, MemberDiff::Delete));
         }
 
         Ok(TeamDiff::Edit(EditTeamDiff {
@@ -273,35 +510,142 @@ impl SyncGitHub {
             name_diff,
             description_diff,
             privacy_diff,
+            parent_diff,
             member_diffs,
         }))
     }
 
     fn diff_repos(&self) -> anyhow::Result<Vec<RepoDiff>> {
-        let mut diffs = Vec::new();
+        let prefetched = if self.config.bulk_repo_reads {
+            Some(Mutex::new(self.fetch_repos_state()?))
+        } else {
+            None
+        };
+        parallel_map(&self.repos, self.config.read_concurrency, |repo| {
+            let state = match &prefetched {
+                Some(states) => states
+                    .lock()
+                    .unwrap()
+                    .remove(&(repo.org.clone(), repo.name.clone())),
+                None => self.github.repo_state(&repo.org, &repo.name)?,
+            };
+            self.diff_repo(repo, state)
+        })
+        .into_iter()
+        .collect()
+    }
+
+    /// Fetch the state of all the repos in the team repo, using the bulk read path of each org.
+    fn fetch_repos_state(&self) -> anyhow::Result<HashMap<(OrgName, RepoName), api::RepoState>> {
+        let mut repos_by_org: HashMap<&str, Vec<&str>> = HashMap::new();
         for repo in &self.repos {
-            diffs.push(self.diff_repo(repo)?);
+            repos_by_org.entry(&repo.org).or_default().push(&repo.name);
+        }
+        let repos_by_org = repos_by_org.into_iter().collect::<Vec<_>>();
+
+        let mut states = HashMap::new();
+        let fetched = parallel_map(
+            &repos_by_org,
+            self.config.read_concurrency,
+            |(org, repos)| self.github.repos_state(org, repos),
+        );
+        for ((org, _), org_states) in repos_by_org.iter().zip(fetched) {
+            for (name, state) in org_states? {
+                states.insert((org.to_string(), name), state);
+            }
         }
-        Ok(diffs)
+        Ok(states)
     }
 
-    fn diff_repo(&self, expected_repo: &rust_team_data::v1::Repo) -> anyhow::Result<RepoDiff> {
-        let actual_repo = match self.github.repo(&expected_repo.org, &expected_repo.name)? {
-            Some(r) => r,
+    fn diff_repo(
+        &self,
+        expected_repo: &rust_team_data::v1::Repo,
+        actual_state: Option<api::RepoState>,
+    ) -> anyhow::Result<RepoDiff> {
+        let config = self.repo_config(expected_repo).cloned().unwrap_or_default();
+        let expected_options = RepoOptions {
+            visibility: Some(if expected_repo.private {
+                RepoVisibility::Private
+            } else {
+                RepoVisibility::Public
+            }),
+            ..RepoOptions::from(&config.options)
+        };
+        let expected_topics = config.topics.as_deref().map(normalize_topics);
+        if config.replace_branch_protections && config.rulesets.is_none() {
+            bail!(
+                "the branch protections of repo {}/{} can't be replaced without rulesets",
+                expected_repo.org,
+                expected_repo.name
+            );
+        }
+        for pattern in config.branch_protections.keys() {
+            if !expected_repo
+                .branch_protections
+                .iter()
+                .any(|protection| &protection.pattern == pattern)
+            {
+                bail!(
+                    "repo {}/{} configures branch protection '{pattern}', which isn't in the \
+                     team repo",
+                    expected_repo.org,
+                    expected_repo.name
+                );
+            }
+        }
+        let mut ruleset_names = HashSet::new();
+        for ruleset in config.rulesets.iter().flatten() {
+            if !ruleset_names.insert(&ruleset.name) {
+                bail!(
+                    "repo {}/{} has multiple rulesets named '{}'",
+                    expected_repo.org,
+                    expected_repo.name,
+                    ruleset.name
+                );
+            }
+        }
+
+        let actual_state = match actual_state {
+            Some(state) => state,
             None => {
                 let permissions = calculate_permission_diffs(
                     expected_repo,
+                    &self.config.bots,
+                    self.org_config(&expected_repo.org),
                     Default::default(),
                     Default::default(),
                 )?;
                 let mut branch_protections = Vec::new();
-                for branch_protection in &expected_repo.branch_protections {
+                for branch_protection in self.expected_branch_protections(expected_repo) {
                     branch_protections.push((
                         branch_protection.pattern.clone(),
-                        construct_branch_protection(expected_repo, branch_protection),
+                        construct_branch_protection(
+                            expected_repo,
+                            &self.config.bots,
+                            branch_protection,
+                            &self.branch_protection_config(
+                                expected_repo,
+                                &branch_protection.pattern,
+                            ),
+                            None,
+                        ),
                     ));
                 }
 
+                let creation = config.creation;
+                if creation.template.is_some()
+                    && (creation.license_template.is_some()
+                        || creation.gitignore_template.is_some())
+                {
+                    bail!(
+                        "repo {}/{} can't be generated from a template with a license or \
+                         .gitignore template",
+                        expected_repo.org,
+                        expected_repo.name
+                    );
+                }
+                check_merge_methods(expected_repo, &expected_options)?;
+
                 return Ok(RepoDiff::Create(CreateRepoDiff {
                     org: expected_repo.org.clone(),
                     name: expected_repo.name.clone(),
@@ -310,7 +654,16 @@ impl SyncGitHub {
                         homepage: expected_repo.homepage.clone(),
                         archived: false,
                         auto_merge_enabled: expected_repo.auto_merge_enabled,
+                        options: expected_options,
                     },
+                    creation,
+                    topics: expected_topics.unwrap_or_default(),
+                    rulesets: config
+                        .rulesets
+                        .into_iter()
+                        .flatten()
+                        .map(Ruleset::normalized)
+                        .collect(),
                     permissions,
                     branch_protections,
                     app_installations: self.diff_app_installations(expected_repo, &[])?,
@@ -318,20 +671,47 @@ impl SyncGitHub {
             }
         };
 
-        let permission_diffs = self.diff_permissions(expected_repo)?;
-        let branch_protection_diffs = self.diff_branch_protections(&actual_repo, expected_repo)?;
+        let actual_repo = actual_state.repo;
+        let permission_diffs = calculate_permission_diffs(
+            expected_repo,
+            &self.config.bots,
+            self.org_config(&expected_repo.org),
+            actual_state
+                .teams
+                .into_iter()
+                .map(|t| (t.name.clone(), t))
+                .collect(),
+            actual_state
+                .collaborators
+                .into_iter()
+                .map(|u| (u.name.clone(), u))
+                .collect(),
+        )?;
+        let branch_protection_diffs =
+            self.diff_branch_protections(actual_state.branch_protections, expected_repo)?;
+        let ruleset_diffs = match &config.rulesets {
+            Some(rulesets) => {
+                self.diff_rulesets(&expected_repo.org, &actual_repo.name, rulesets)?
+            }
+            None => Vec::new(),
+        };
+        let topics_diff =
+            expected_topics.and_then(|expected| TopicsDiff::new(&actual_state.topics, expected));
         let old_settings = RepoSettings {
             description: actual_repo.description.clone(),
             homepage: actual_repo.homepage.clone(),
             archived: actual_repo.archived,
             auto_merge_enabled: actual_repo.allow_auto_merge.unwrap_or(false),
+            options: actual_repo.options.clone(),
         };
         let new_settings = RepoSettings {
             description: Some(expected_repo.description.clone()),
             homepage: expected_repo.homepage.clone(),
             archived: expected_repo.archived,
             auto_merge_enabled: expected_repo.auto_merge_enabled,
+            options: actual_repo.options.overridden_by(&expected_options),
         };
+        check_merge_methods(expected_repo, &new_settings.options)?;
 
         let existing_installations = self
             .org_apps
@@ -340,11 +720,9 @@ impl SyncGitHub {
                 installations
                     .iter()
                     .filter_map(|installation| {
-                        // Only load installations from apps that we know about, to avoid removing
-                        // unknown installations.
                         if installation.repositories.contains(&actual_repo.name) {
                             Some(AppInstallation {
-                                app: installation.app,
+                                app: installation.app.clone(),
                                 installation_id: installation.installation_id,
                             })
                         } else {
@@ -362,45 +740,29 @@ impl SyncGitHub {
             repo_node_id: actual_repo.node_id,
             repo_id: actual_repo.repo_id,
             settings_diff: (old_settings, new_settings),
+            topics_diff,
             permission_diffs,
+            ruleset_diffs,
             branch_protection_diffs,
             app_installation_diffs,
         }))
     }
 
-    fn diff_permissions(
-        &self,
-        expected_repo: &rust_team_data::v1::Repo,
-    ) -> anyhow::Result<Vec<RepoPermissionAssignmentDiff>> {
-        let actual_teams: HashMap<_, _> = self
-            .github
-            .repo_teams(&expected_repo.org, &expected_repo.name)?
-            .into_iter()
-            .map(|t| (t.name.clone(), t))
-            .collect();
-        let actual_collaborators: HashMap<_, _> = self
-            .github
-            .repo_collaborators(&expected_repo.org, &expected_repo.name)?
-            .into_iter()
-            .map(|u| (u.name.clone(), u))
-            .collect();
-
-        calculate_permission_diffs(expected_repo, actual_teams, actual_collaborators)
-    }
-
     fn diff_branch_protections(
         &self,
-        actual_repo: &api::Repo,
+        mut actual_protections: HashMap<String, (String, api::BranchProtection)>,
         expected_repo: &rust_team_data::v1::Repo,
     ) -> anyhow::Result<Vec<BranchProtectionDiff>> {
         let mut branch_protection_diffs = Vec::new();
-        let mut actual_protections = self
-            .github
-            .branch_protections(&actual_repo.org, &actual_repo.name)?;
-        for branch_protection in &expected_repo.branch_protections {
+        for branch_protection in self.expected_branch_protections(expected_repo) {
             let actual_branch_protection = actual_protections.remove(&branch_protection.pattern);
-            let expected_branch_protection =
-                construct_branch_protection(expected_repo, branch_protection);
+            let expected_branch_protection = construct_branch_protection(
+                expected_repo,
+                &self.config.bots,
+                branch_protection,
+                &self.branch_protection_config(expected_repo, &branch_protection.pattern),
+                actual_branch_protection.as_ref().map(|(_, bp)| bp),
+            );
             let operation = {
                 match actual_branch_protection {
                     Some((database_id, bp)) if bp != expected_branch_protection => {
@@ -423,6 +785,8 @@ impl SyncGitHub {
 
         // `actual_branch_protections` now contains the branch protections that were not expected
         // but are still on GitHub. We want to delete them.
+        let mut actual_protections = actual_protections.into_iter().collect::<Vec<_>>();
+        actual_protections.sort_by(|(a, _), (b, _)| a.cmp(b));
         branch_protection_diffs.extend(actual_protections.into_iter().map(|(name, (id, _))| {
             BranchProtectionDiff {
                 pattern: name,
@@ -433,6 +797,66 @@ impl SyncGitHub {
         Ok(branch_protection_diffs)
     }
 
+    /// The settings of a branch protection that the team repo doesn't define.
+    fn branch_protection_config(
+        &self,
+        expected_repo: &rust_team_data::v1::Repo,
+        pattern: &str,
+    ) -> BranchProtectionConfig {
+        self.repo_config(expected_repo)
+            .and_then(|config| config.branch_protections.get(pattern))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// The branch protections of the team repo that are synchronized, which is none of them
+    /// once they're replaced by rulesets.
+    fn expected_branch_protections<'a>(
+        &self,
+        expected_repo: &'a rust_team_data::v1::Repo,
+    ) -> &'a [rust_team_data::v1::BranchProtection] {
+        match self.repo_config(expected_repo) {
+            Some(config) if config.replace_branch_protections => &[],
+            _ => &expected_repo.branch_protections,
+        }
+    }
+
+    fn diff_rulesets(
+        &self,
+        org: &str,
+        repo_name: &str,
+        expected_rulesets: &[Ruleset],
+    ) -> anyhow::Result<Vec<RulesetDiff>> {
+        let mut actual_rulesets = self.github.repo_rulesets(org, repo_name)?;
+        let mut ruleset_diffs = Vec::new();
+        for expected in expected_rulesets {
+            let expected = expected.clone().normalized();
+            let operation = match actual_rulesets.remove(&expected.name) {
+                Some((id, actual)) if actual != expected => {
+                    RulesetDiffOperation::Update(id, actual, expected.clone())
+                }
+                Some(_) => continue,
+                None => RulesetDiffOperation::Create(expected.clone()),
+            };
+            ruleset_diffs.push(RulesetDiff {
+                name: expected.name,
+                operation,
+            });
+        }
+
+        let mut actual_rulesets = actual_rulesets.into_iter().collect::<Vec<_>>();
+        actual_rulesets.sort_by(|(a, _), (b, _)| a.cmp(b));
+        ruleset_diffs.extend(
+            actual_rulesets
+                .into_iter()
+                .map(|(name, (id, _))| RulesetDiff {
+                    name,
+                    operation: RulesetDiffOperation::Delete(id),
+                }),
+        );
+        Ok(ruleset_diffs)
+    }
+
     fn diff_app_installations(
         &self,
         expected_repo: &rust_team_data::v1::Repo,
@@ -441,11 +865,27 @@ impl SyncGitHub {
         let mut diff = vec![];
         let mut found_apps = Vec::new();
 
-        // Find apps that should be enabled on the repository
-        for app in expected_repo.bots.iter().filter_map(|bot| match bot {
-            Bot::Renovate => Some(GithubApp::RenovateBot),
-            _ => None,
+        let repo_config = self.repo_config(expected_repo);
+        let enabled_apps = repo_config.map_or(&[][..], |config| &config.apps);
+        for name in enabled_apps {
+            if !self.config.apps.iter().any(|app| &app.name == name) {
+                bail!(
+                    "repo {}/{} enables app {name}, which is missing from github.apps",
+                    expected_repo.org,
+                    expected_repo.name
+                );
+            }
+        }
+
+        // Find apps that should be enabled on the repository, either by one of its bots or by its
+        // configuration.
+        for app in self.config.apps.iter().filter(|app| {
+            app.bot
+                .as_ref()
+                .map_or(false, |bot| expected_repo.bots.contains(bot))
+                || enabled_apps.contains(&app.name)
         }) {
+            let app = &app.name;
             // Find installation ID of this app on GitHub
             let gh_installation = self
                 .org_apps
@@ -453,7 +893,7 @@ impl SyncGitHub {
                 .and_then(|installations| {
                     installations
                         .iter()
-                        .find(|installation| installation.app == app)
+                        .find(|installation| &installation.app == app)
                         .map(|i| i.installation_id)
                 });
             let Some(gh_installation) = gh_installation else {
@@ -461,7 +901,7 @@ impl SyncGitHub {
                 continue;
             };
             let installation = AppInstallation {
-                app,
+                app: app.clone(),
                 installation_id: gh_installation,
             };
             found_apps.push(installation.clone());
@@ -479,6 +919,65 @@ impl SyncGitHub {
         Ok(diff)
     }
 
+    /// The policy of an org in `github.orgs`, which doesn't touch anything by default.
+    fn org_config(&self, org: &str) -> &OrgConfig {
+        self.config.orgs.get(org).unwrap_or(&DEFAULT_ORG_CONFIG)
+    }
+
+    /// The GitHub name of a user of the team repo.
+    fn username(&self, id: u64) -> anyhow::Result<&String> {
+        self.usernames_cache
+            .get(&id)
+            .with_context(|| format!("GitHub user with ID {id} not found"))
+    }
+
+    /// The configuration of a repo in `github.repos`, if any.
+    fn repo_config(&self, repo: &rust_team_data::v1::Repo) -> Option<&RepoConfig> {
+        self.config
+            .repos
+            .get(&format!("{}/{}", repo.org, repo.name))
+    }
+
+    /// The GitHub team a GitHub team should be nested in: the first GitHub team in the same org
+    /// of the closest ancestor of its team that has one.
+    fn expected_parent(&self, org: &str, name: &str) -> Option<&str> {
+        let team_of = |org: &str, name: &str| {
+            self.teams.iter().find(|team| {
+                team.github.as_ref().map_or(false, |gh| {
+                    gh.teams.iter().any(|t| t.org == org && t.name == name)
+                })
+            })
+        };
+        let mut team = team_of(org, name)?;
+        // The team repo doesn't allow cycles, but don't loop forever if there is one.
+        for _ in 0..self.teams.len() {
+            let parent_name = team.subteam_of.as_deref()?;
+            team = self.teams.iter().find(|t| t.name == parent_name)?;
+            let parent_gh_team = team
+                .github
+                .as_ref()
+                .and_then(|gh| gh.teams.iter().find(|t| t.org == org));
+            if let Some(parent_gh_team) = parent_gh_team {
+                return Some(&parent_gh_team.name);
+            }
+        }
+        None
+    }
+
+    /// How many parents a GitHub team is nested in.
+    fn nesting_depth(&self, org: &str, name: &str) -> usize {
+        let mut depth = 0;
+        let mut current = name;
+        while let Some(parent) = self.expected_parent(org, current) {
+            depth += 1;
+            current = parent;
+            if depth > self.teams.len() {
+                break;
+            }
+        }
+        depth
+    }
+
     fn expected_role(&self, org: &str, user: u64) -> TeamRole {
         if let Some(true) = self
             .org_owners
@@ -494,13 +993,31 @@ impl SyncGitHub {
 
 fn calculate_permission_diffs(
     expected_repo: &rust_team_data::v1::Repo,
+    bots: &[BotConfig],
+    org_config: &OrgConfig,
     mut actual_teams: HashMap<String, api::RepoTeam>,
     mut actual_collaborators: HashMap<String, api::RepoUser>,
 ) -> anyhow::Result<Vec<RepoPermissionAssignmentDiff>> {
     let mut permissions = Vec::new();
     // Team permissions
     for expected_team in &expected_repo.teams {
-        let permission = convert_permission(&expected_team.permission);
+        let mut permission = convert_permission(&expected_team.permission);
+        let ceiling = org_config
+            .team_permissions
+            .get(&expected_team.name)
+            .and_then(|policy| policy.ceiling);
+        if let Some(ceiling) = ceiling {
+            if permission > ceiling {
+                log::warn!(
+                    "team '{}' can't have {permission} permission on repo {}/{}, as it's limited \
+                     to {ceiling}",
+                    expected_team.name,
+                    expected_repo.org,
+                    expected_repo.name
+                );
+                permission = ceiling;
+            }
+        }
         let actual_team = actual_teams.remove(&expected_team.name);
         let collaborator = RepoCollaborator::Team(expected_team.name.clone());
 
@@ -520,9 +1037,10 @@ fn calculate_permission_diffs(
     }
     // Bot permissions
     let bots = expected_repo.bots.iter().filter_map(|b| {
-        let bot_user_name = bot_user_name(b)?;
-        actual_teams.remove(bot_user_name);
-        Some((bot_user_name, RepoPermission::Write))
+        // Bots without a user are GitHub apps.
+        let bot = bots.iter().find(|config| config.bot == *b)?;
+        actual_teams.remove(&bot.user);
+        Some((bot.user.as_str(), bot.permission))
     });
     // Member permissions
     let members = expected_repo
@@ -548,16 +1066,27 @@ fn calculate_permission_diffs(
     }
     // `actual_teams` now contains the teams that were not expected
     // but are still on GitHub. We now remove them.
+    let mut actual_teams = actual_teams.into_iter().collect::<Vec<_>>();
+    actual_teams.sort_by(|(a, _), (b, _)| a.cmp(b));
     for (team, t) in actual_teams {
-        if t.name == "security" && expected_repo.org == "rust-lang" {
-            // Skip removing access permissions from security.
-            // If we're in this branch we know that the team repo doesn't mention this team at all,
-            // so this shouldn't remove intentionally granted non-read access.  Security is granted
-            // read access to all repositories in the org by GitHub (via a "security manager"
-            // role), and we can't remove that access.
-            //
-            // (FIXME: If we find security with non-read access, *that* probably should get dropped
-            // to read access. But not worth doing in this commit, want to get us unblocked first).
+        let floor = org_config
+            .team_permissions
+            .get(&team)
+            .and_then(|policy| policy.floor);
+        if let Some(floor) = floor {
+            // The team repo doesn't mention this team at all, but some of its access can't be
+            // removed, like the read access GitHub gives to security in rust-lang (via a
+            // "security manager" role). Anything above that was granted manually though, and is
+            // downgraded.
+            if t.permission > floor {
+                permissions.push(RepoPermissionAssignmentDiff {
+                    collaborator: RepoCollaborator::Team(team),
+                    diff: RepoPermissionDiff::Update(t.permission, floor),
+                });
+            }
+            continue;
+        }
+        if org_config.ignored_teams.contains(&t.name) {
             continue;
         }
         permissions.push(RepoPermissionAssignmentDiff {
@@ -567,7 +1096,16 @@ fn calculate_permission_diffs(
     }
     // `actual_collaborators` now contains the collaborators that were not expected
     // but are still on GitHub. We now remove them.
+    let mut actual_collaborators = actual_collaborators.into_iter().collect::<Vec<_>>();
+    actual_collaborators.sort_by(|(a, _), (b, _)| a.cmp(b));
     for (collaborator, u) in actual_collaborators {
+        if org_config
+            .ignored_collaborators
+            .iter()
+            .any(|ignored| ignored.eq_ignore_ascii_case(&collaborator))
+        {
+            continue;
+        }
         permissions.push(RepoPermissionAssignmentDiff {
             collaborator: RepoCollaborator::User(collaborator),
             diff: RepoPermissionDiff::Delete(u.permission),
@@ -576,16 +1114,16 @@ fn calculate_permission_diffs(
     Ok(permissions)
 }
 
-/// Returns `None` if the bot is not an actual bot user, but rather a GitHub app.
-fn bot_user_name(bot: &Bot) -> Option<&str> {
-    match bot {
-        Bot::Bors => Some("bors"),
-        Bot::Highfive => Some("rust-highfive"),
-        Bot::RustTimer => Some("rust-timer"),
-        Bot::Rustbot => Some("rustbot"),
-        Bot::Rfcbot => Some("rfcbot"),
-        Bot::Renovate => None,
-    }
+/// The description and privacy of the GitHub teams of a team, taken from the team data. The team
+/// data doesn't have a privacy, so every team keeps the default one.
+fn team_settings(team: &rust_team_data::v1::Team) -> (&str, TeamPrivacy) {
+    let description = team
+        .website_data
+        .as_ref()
+        .map(|website| website.description.as_str())
+        .filter(|description| !description.is_empty())
+        .unwrap_or(DEFAULT_DESCRIPTION);
+    (description, DEFAULT_PRIVACY)
 }
 
 fn convert_permission(p: &rust_team_data::v1::RepoPermission) -> RepoPermission {
@@ -598,9 +1136,35 @@ fn convert_permission(p: &rust_team_data::v1::RepoPermission) -> RepoPermission
     }
 }
 
+/// GitHub requires at least one merge method to be allowed, so reject the options that would
+/// disable all of them, once combined with the current ones.
+fn check_merge_methods(
+    expected_repo: &rust_team_data::v1::Repo,
+    options: &RepoOptions,
+) -> anyhow::Result<()> {
+    let merge_methods = [
+        options.allow_merge_commit,
+        options.allow_squash_merge,
+        options.allow_rebase_merge,
+    ];
+    if merge_methods.iter().all(|allowed| *allowed == Some(false)) {
+        bail!(
+            "repo {}/{} must allow at least one merge method",
+            expected_repo.org,
+            expected_repo.name
+        );
+    }
+    Ok(())
+}
+
+/// The branch protection expected on GitHub. The settings that aren't configured keep their value
+/// in the `actual` branch protection, if it exists.
 fn construct_branch_protection(
     expected_repo: &rust_team_data::v1::Repo,
+    bots: &[BotConfig],
     branch_protection: &rust_team_data::v1::BranchProtection,
+    config: &BranchProtectionConfig,
+    actual: Option<&api::BranchProtection>,
 ) -> api::BranchProtection {
     let uses_bors = expected_repo.bots.contains(&Bot::Bors);
     let required_approving_review_count: u8 = if uses_bors {
@@ -615,31 +1179,61 @@ fn construct_branch_protection(
             BranchProtectionMode::PrNotRequired => 0,
         }
     };
+    let team_actor = |team: &String| {
+        PushAllowanceActor::Team(api::TeamPushAllowanceActor {
+            organization: Login {
+                login: expected_repo.org.clone(),
+            },
+            name: team.clone(),
+        })
+    };
     let mut push_allowances: Vec<PushAllowanceActor> = branch_protection
         .allowed_merge_teams
         .iter()
-        .map(|team| {
-            api::PushAllowanceActor::Team(api::TeamPushAllowanceActor {
-                organization: Login {
-                    login: expected_repo.org.clone(),
-                },
-                name: team.to_string(),
-            })
-        })
+        .map(team_actor)
         .collect();
 
     if uses_bors {
-        push_allowances.push(PushAllowanceActor::User(api::UserPushAllowanceActor {
-            login: "bors".to_owned(),
-        }));
+        if let Some(bors) = bots.iter().find(|config| config.bot == Bot::Bors) {
+            push_allowances.push(PushAllowanceActor::User(api::UserPushAllowanceActor {
+                login: bors.user.clone(),
+            }));
+        }
     }
+    let review_dismissal_allowances = match (
+        &config.review_dismissal_teams,
+        &config.review_dismissal_users,
+    ) {
+        (None, None) => actual
+            .map(|bp| bp.review_dismissal_allowances.clone())
+            .unwrap_or_default(),
+        (teams, users) => teams
+            .iter()
+            .flatten()
+            .map(team_actor)
+            .chain(users.iter().flatten().map(|user| {
+                PushAllowanceActor::User(api::UserPushAllowanceActor {
+                    login: user.clone(),
+                })
+            }))
+            .collect(),
+    };
+    let setting = |configured: Option<bool>, current: fn(&api::BranchProtection) -> bool| {
+        configured.unwrap_or_else(|| actual.map_or(false, current))
+    };
     api::BranchProtection {
         pattern: branch_protection.pattern.clone(),
-        is_admin_enforced: true,
+        is_admin_enforced: config.is_admin_enforced,
         dismisses_stale_reviews: branch_protection.dismiss_stale_review,
         required_approving_review_count,
-        required_status_check_contexts: match &branch_protection.mode {
-            BranchProtectionMode::PrRequired { ci_checks, .. } => ci_checks.clone(),
+        required_status_checks: match &branch_protection.mode {
+            BranchProtectionMode::PrRequired { ci_checks, .. } => ci_checks
+                .iter()
+                .map(|context| api::RequiredStatusCheck {
+                    context: context.clone(),
+                    app_id: config.status_checks_app_id,
+                })
+                .collect(),
             BranchProtectionMode::PrNotRequired => {
                 vec![]
             }
@@ -649,34 +1243,159 @@ fn construct_branch_protection(
             branch_protection.mode,
             BranchProtectionMode::PrRequired { .. }
         ),
+        requires_strict_status_checks: setting(config.requires_strict_status_checks, |bp| {
+            bp.requires_strict_status_checks
+        }),
+        requires_code_owner_reviews: setting(config.requires_code_owner_reviews, |bp| {
+            bp.requires_code_owner_reviews
+        }),
+        requires_linear_history: setting(config.requires_linear_history, |bp| {
+            bp.requires_linear_history
+        }),
+        requires_conversation_resolution: setting(config.requires_conversation_resolution, |bp| {
+            bp.requires_conversation_resolution
+        }),
+        allows_force_pushes: setting(config.allows_force_pushes, |bp| bp.allows_force_pushes),
+        allows_deletions: setting(config.allows_deletions, |bp| bp.allows_deletions),
+        review_dismissal_allowances,
     }
 }
 
-/// The special bot teams
-const BOTS_TEAMS: &[&str] = &["bors", "highfive", "rfcbot", "bots"];
-
 /// A diff between the team repo and the state on GitHub
+#[derive(serde::Serialize)]
 pub(crate) struct Diff {
+    /// Including the unmanaged members, which are only reported.
+    org_membership_diffs: Vec<OrgMembershipDiff>,
+    #[serde(serialize_with = "serialize_team_diffs")]
     team_diffs: Vec<TeamDiff>,
+    #[serde(serialize_with = "serialize_repo_diffs")]
     repo_diffs: Vec<RepoDiff>,
+    /// Repos not in the team repo, which are only reported.
+    unmanaged_repos: Vec<UnmanagedRepo>,
 }
 
 impl Diff {
     /// Apply the diff to GitHub
-    pub(crate) fn apply(self, sync: &GitHubWrite) -> anyhow::Result<()> {
+    pub(crate) fn apply(self, sync: &GitHubWrite, failures: &mut Failures) -> anyhow::Result<()> {
+        for org_membership_diff in &self.org_membership_diffs {
+            failures.handle(org_membership_diff.apply(sync), || {
+                org_membership_diff.context()
+            })?;
+        }
         for team_diff in self.team_diffs {
-            team_diff.apply(sync)?;
+            team_diff.apply(sync, failures)?;
         }
         for repo_diff in self.repo_diffs {
-            repo_diff.apply(sync)?;
+            repo_diff.apply(sync, failures)?;
         }
 
         Ok(())
     }
+
+    /// Fail if new unmanaged repos were found in the orgs that don't allow them.
+    fn check(&self) -> anyhow::Result<()> {
+        let new_repos = self
+            .unmanaged_repos
+            .iter()
+            .filter(|repo| repo.fail)
+            .map(|repo| format!("{}/{}", repo.org, repo.name))
+            .collect::<Vec<_>>();
+        if !new_repos.is_empty() {
+            bail!("found new unmanaged repos: {}", new_repos.join(", "));
+        }
+        Ok(())
+    }
+
+    fn removals(&self) -> Removals {
+        let mut removals = Removals::default();
+        for diff in &self.org_membership_diffs {
+            if let OrgMembershipDiffOperation::Remove = diff.operation {
+                removals
+                    .org_member_removals
+                    .push(format!("{} from {}", diff.member, diff.org));
+            }
+        }
+        for team_diff in &self.team_diffs {
+            match team_diff {
+                TeamDiff::Delete(d) => removals
+                    .team_deletions
+                    .push(format!("{}/{}", d.org, d.name)),
+                TeamDiff::Edit(e) => {
+                    let count = |f: fn(&MemberDiff) -> bool| {
+                        e.member_diffs.iter().filter(|(_, d)| f(d)).count()
+                    };
+                    removals.group(
+                        format!("team {}/{}", e.org, e.name),
+                        count(|d| !matches!(d, MemberDiff::Create(_))),
+                        count(|d| matches!(d, MemberDiff::Delete)),
+                    );
+                }
+                TeamDiff::Create(_) => {}
+            }
+        }
+        for repo_diff in &self.repo_diffs {
+            let RepoDiff::Update(d) = repo_diff else {
+                continue;
+            };
+            if !d.can_be_modified() {
+                continue;
+            }
+            let repo = format!("{}/{}", d.org, d.name);
+            for permission in &d.permission_diffs {
+                if let RepoPermissionDiff::Delete(_) = permission.diff {
+                    removals
+                        .permission_removals
+                        .push(format!("{} from {repo}", permission.collaborator));
+                }
+            }
+            for ruleset in &d.ruleset_diffs {
+                if let RulesetDiffOperation::Delete(_) = ruleset.operation {
+                    removals
+                        .repo_setting_deletions
+                        .push(format!("ruleset '{}' of {repo}", ruleset.name));
+                }
+            }
+            for protection in &d.branch_protection_diffs {
+                if let BranchProtectionDiffOperation::Delete(_) = protection.operation {
+                    removals.repo_setting_deletions.push(format!(
+                        "branch protection '{}' of {repo}",
+                        protection.pattern
+                    ));
+                }
+            }
+            for app in &d.app_installation_diffs {
+                if let AppInstallationDiff::Remove(app) = app {
+                    removals
+                        .repo_setting_deletions
+                        .push(format!("app {} from {repo}", app.app));
+                }
+            }
+        }
+        removals
+    }
+}
+
+/// Only serialize the diffs that would actually change something, like the `Display` impl does.
+fn serialize_team_diffs<S: serde::Serializer>(
+    diffs: &[TeamDiff],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(diffs.iter().filter(|d| !d.noop()))
+}
+
+fn serialize_repo_diffs<S: serde::Serializer>(
+    diffs: &[RepoDiff],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(diffs.iter().filter(|d| !d.noop()))
 }
 
 impl std::fmt::Display for Diff {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "💻 Org Membership Diffs:")?;
+        for org_membership_diff in &self.org_membership_diffs {
+            write!(f, "{org_membership_diff}")?;
+        }
         writeln!(f, "💻 Team Diffs:")?;
         for team_diff in &self.team_diffs {
             write!(f, "{team_diff}")?;
@@ -685,20 +1404,68 @@ impl std::fmt::Display for Diff {
         for repo_diff in &self.repo_diffs {
             write!(f, "{repo_diff}")?;
         }
+        if !self.unmanaged_repos.is_empty() {
+            writeln!(f, "💻 Unmanaged Repos:")?;
+        }
+        for repo in &self.unmanaged_repos {
+            write!(f, "{repo}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A repo of a managed org that is not in the team repo
+#[derive(serde::Serialize)]
+struct UnmanagedRepo {
+    org: String,
+    name: String,
+    visibility: Option<api::RepoVisibility>,
+    archived: bool,
+    /// Direct collaborators with admin permission
+    admins: Vec<String>,
+    /// Whether the repo is missing from the known unmanaged repos of its org.
+    new: bool,
+    /// Whether the repo fails the run, as its org doesn't allow new unmanaged repos.
+    fail: bool,
+}
+
+impl std::fmt::Display for UnmanagedRepo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let marker = if self.new { "🆕" } else { "❔" };
+        write!(f, "{marker} '{}/{}'", self.org, self.name)?;
+        if let Some(visibility) = self.visibility {
+            write!(f, " ({visibility})")?;
+        }
+        if self.archived {
+            write!(f, " (archived)")?;
+        }
+        writeln!(f)?;
+        if !self.admins.is_empty() {
+            writeln!(f, "  Admins: {}", self.admins.join(", "))?;
+        }
         Ok(())
     }
 }
 
+#[derive(serde::Serialize)]
+#[serde(rename_all = "snake_case")]
 enum RepoDiff {
     Create(CreateRepoDiff),
     Update(UpdateRepoDiff),
 }
 
 impl RepoDiff {
-    fn apply(&self, sync: &GitHubWrite) -> anyhow::Result<()> {
+    fn apply(&self, sync: &GitHubWrite, failures: &mut Failures) -> anyhow::Result<()> {
         match self {
-            RepoDiff::Create(c) => c.apply(sync),
-            RepoDiff::Update(u) => u.apply(sync),
+            RepoDiff::Create(c) => c.apply(sync, failures),
+            RepoDiff::Update(u) => u.apply(sync, failures),
+        }
+    }
+
+    fn noop(&self) -> bool {
+        match self {
+            RepoDiff::Create(_) => false,
+            RepoDiff::Update(u) => u.noop(),
         }
     }
 }
@@ -712,33 +1479,60 @@ impl std::fmt::Display for RepoDiff {
     }
 }
 
+#[derive(serde::Serialize)]
 struct CreateRepoDiff {
     org: String,
     name: String,
     settings: RepoSettings,
+    creation: RepoCreationSettings,
+    topics: Vec<String>,
     permissions: Vec<RepoPermissionAssignmentDiff>,
+    rulesets: Vec<Ruleset>,
     branch_protections: Vec<(String, api::BranchProtection)>,
     app_installations: Vec<AppInstallationDiff>,
 }
 
 impl CreateRepoDiff {
-    fn apply(&self, sync: &GitHubWrite) -> anyhow::Result<()> {
-        let repo = sync.create_repo(&self.org, &self.name, &self.settings)?;
+    fn apply(&self, sync: &GitHubWrite, failures: &mut Failures) -> anyhow::Result<()> {
+        let (org, name) = (&self.org, &self.name);
+        let created = sync.create_repo(org, name, &self.settings, &self.creation);
+        let Some(repo) = failures.handle(created, || format!("creating repo {org}/{name}"))? else {
+            return Ok(());
+        };
+
+        if !self.topics.is_empty() {
+            let result = sync.set_repo_topics(org, name, &self.topics);
+            failures.handle(result, || {
+                format!("setting the topics of repo {org}/{name}")
+            })?;
+        }
 
         for permission in &self.permissions {
-            permission.apply(sync, &self.org, &self.name)?;
+            let result = permission.apply(sync, org, name);
+            failures.handle(result, || permission.context(org, name))?;
+        }
+
+        for ruleset in &self.rulesets {
+            let diff = RulesetDiff {
+                name: ruleset.name.clone(),
+                operation: RulesetDiffOperation::Create(ruleset.clone()),
+            };
+            let result = diff.apply(sync, org, name);
+            failures.handle(result, || diff.context(org, name))?;
         }
 
         for (branch, protection) in &self.branch_protections {
-            BranchProtectionDiff {
+            let diff = BranchProtectionDiff {
                 pattern: branch.clone(),
                 operation: BranchProtectionDiffOperation::Create(protection.clone()),
-            }
-            .apply(sync, &self.org, &self.name, &repo.node_id)?;
+            };
+            let result = diff.apply(sync, org, name, &repo.node_id);
+            failures.handle(result, || diff.context(org, name))?;
         }
 
         for installation in &self.app_installations {
-            installation.apply(sync, repo.repo_id)?;
+            let result = installation.apply(sync, repo.repo_id);
+            failures.handle(result, || installation.context(org, name))?;
         }
 
         Ok(())
@@ -752,7 +1546,14 @@ impl std::fmt::Display for CreateRepoDiff {
             homepage,
             archived: _,
             auto_merge_enabled,
+            options,
         } = &self.settings;
+        let RepoCreationSettings {
+            template,
+            default_branch,
+            license_template,
+            gitignore_template,
+        } = &self.creation;
 
         writeln!(f, "➕ Creating repo:")?;
         writeln!(f, "  Org: {}", self.org)?;
@@ -760,10 +1561,33 @@ impl std::fmt::Display for CreateRepoDiff {
         writeln!(f, "  Description: {:?}", description)?;
         writeln!(f, "  Homepage: {:?}", homepage)?;
         writeln!(f, "  Auto-merge: {}", auto_merge_enabled)?;
+        log_repo_options(options, None, &mut f)?;
+        if let Some(template) = template {
+            writeln!(f, "  Template: {template}")?;
+        }
+        if let Some(default_branch) = default_branch {
+            writeln!(f, "  Default branch: {default_branch}")?;
+        }
+        if let Some(license_template) = license_template {
+            writeln!(f, "  License: {license_template}")?;
+        }
+        if let Some(gitignore_template) = gitignore_template {
+            writeln!(f, "  Gitignore: {gitignore_template}")?;
+        }
+        if !self.topics.is_empty() {
+            writeln!(f, "  Topics: {}", self.topics.join(", "))?;
+        }
         writeln!(f, "  Permissions:")?;
         for diff in &self.permissions {
             write!(f, "{diff}")?;
         }
+        if !self.rulesets.is_empty() {
+            writeln!(f, "  Rulesets:")?;
+        }
+        for ruleset in &self.rulesets {
+            writeln!(&mut f, "    {}", ruleset.name)?;
+            log_ruleset(ruleset, None, &mut f)?;
+        }
         writeln!(f, "  Branch Protections:")?;
         for (branch_name, branch_protection) in &self.branch_protections {
             writeln!(&mut f, "    {branch_name}")?;
@@ -777,6 +1601,7 @@ impl std::fmt::Display for CreateRepoDiff {
     }
 }
 
+#[derive(serde::Serialize)]
 struct UpdateRepoDiff {
     org: String,
     name: String,
@@ -784,7 +1609,9 @@ struct UpdateRepoDiff {
     repo_id: u64,
     // old, new
     settings_diff: (RepoSettings, RepoSettings),
+    topics_diff: Option<TopicsDiff>,
     permission_diffs: Vec<RepoPermissionAssignmentDiff>,
+    ruleset_diffs: Vec<RulesetDiff>,
     branch_protection_diffs: Vec<BranchProtectionDiff>,
     app_installation_diffs: Vec<AppInstallationDiff>,
 }
@@ -796,7 +1623,9 @@ impl UpdateRepoDiff {
         }
 
         self.settings_diff.0 == self.settings_diff.1
+            && self.topics_diff.is_none()
             && self.permission_diffs.is_empty()
+            && self.ruleset_diffs.is_empty()
             && self.branch_protection_diffs.is_empty()
             && self.app_installation_diffs.is_empty()
     }
@@ -811,24 +1640,56 @@ impl UpdateRepoDiff {
         true
     }
 
-    fn apply(&self, sync: &GitHubWrite) -> anyhow::Result<()> {
+    fn apply(&self, sync: &GitHubWrite, failures: &mut Failures) -> anyhow::Result<()> {
         if !self.can_be_modified() {
             return Ok(());
         }
 
+        let (org, name) = (&self.org, &self.name);
         if self.settings_diff.0 != self.settings_diff.1 {
-            sync.edit_repo(&self.org, &self.name, &self.settings_diff.1)?;
+            let (old, new) = &self.settings_diff;
+            let result = sync.edit_repo(org, name, old, new);
+            failures.handle(result, || {
+                format!("editing the settings of repo {org}/{name}")
+            })?;
+        }
+        if let Some(topics_diff) = &self.topics_diff {
+            let result = sync.set_repo_topics(org, name, &topics_diff.topics);
+            failures.handle(result, || {
+                format!("updating the topics of repo {org}/{name}")
+            })?;
         }
         for permission in &self.permission_diffs {
-            permission.apply(sync, &self.org, &self.name)?;
+            let result = permission.apply(sync, org, name);
+            failures.handle(result, || permission.context(org, name))?;
+        }
+
+        let mut rulesets_applied = true;
+        for ruleset in &self.ruleset_diffs {
+            let result = ruleset.apply(sync, org, name);
+            rulesets_applied &= failures
+                .handle(result, || ruleset.context(org, name))?
+                .is_some();
         }
 
         for branch_protection in &self.branch_protection_diffs {
-            branch_protection.apply(sync, &self.org, &self.name, &self.repo_node_id)?;
+            // Don't leave branches unprotected when the rulesets replacing their protection
+            // failed to apply.
+            if !rulesets_applied
+                && matches!(
+                    branch_protection.operation,
+                    BranchProtectionDiffOperation::Delete(_)
+                )
+            {
+                continue;
+            }
+            let result = branch_protection.apply(sync, org, name, &self.repo_node_id);
+            failures.handle(result, || branch_protection.context(org, name))?;
         }
 
         for app_installation in &self.app_installation_diffs {
-            app_installation.apply(sync, self.repo_id)?;
+            let result = app_installation.apply(sync, self.repo_id);
+            failures.handle(result, || app_installation.context(org, name))?;
         }
         Ok(())
     }
@@ -846,6 +1707,7 @@ impl std::fmt::Display for UpdateRepoDiff {
             homepage,
             archived,
             auto_merge_enabled,
+            options,
         } = settings_old;
         match (description, &settings_new.description) {
             (None, Some(new)) => writeln!(f, "  Set description: '{new}'")?,
@@ -873,12 +1735,22 @@ impl std::fmt::Display for UpdateRepoDiff {
             (true, false) => writeln!(f, "  Disable auto-merge")?,
             _ => {}
         }
+        log_repo_options(options, Some(&settings_new.options), &mut *f)?;
+        if let Some(topics_diff) = &self.topics_diff {
+            write!(f, "{topics_diff}")?;
+        }
         if !self.permission_diffs.is_empty() {
             writeln!(f, "  Permission Changes:")?;
         }
         for permission_diff in &self.permission_diffs {
             write!(f, "{permission_diff}")?;
         }
+        if !self.ruleset_diffs.is_empty() {
+            writeln!(f, "  Rulesets:")?;
+        }
+        for ruleset_diff in &self.ruleset_diffs {
+            write!(f, "{ruleset_diff}")?;
+        }
         if !self.branch_protection_diffs.is_empty() {
             writeln!(f, "  Branch Protections:")?;
         }
@@ -896,12 +1768,76 @@ impl std::fmt::Display for UpdateRepoDiff {
     }
 }
 
+/// Lowercase, sort and deduplicate topics the way GitHub stores them.
+fn normalize_topics(topics: &[String]) -> Vec<String> {
+    topics
+        .iter()
+        .map(|topic| topic.to_lowercase())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+#[derive(serde::Serialize)]
+struct TopicsDiff {
+    /// All the topics of the repo after the diff is applied, as GitHub replaces them at once.
+    topics: Vec<String>,
+    additions: Vec<String>,
+    removals: Vec<String>,
+}
+
+impl TopicsDiff {
+    /// Returns `None` if the repo already has the expected topics.
+    fn new(actual: &[String], expected: Vec<String>) -> Option<Self> {
+        let additions: Vec<_> = expected
+            .iter()
+            .filter(|topic| !actual.contains(topic))
+            .cloned()
+            .collect();
+        let mut removals: Vec<_> = actual
+            .iter()
+            .filter(|topic| !expected.contains(topic))
+            .cloned()
+            .collect();
+        removals.sort();
+        if additions.is_empty() && removals.is_empty() {
+            return None;
+        }
+        Some(Self {
+            topics: expected,
+            additions,
+            removals,
+        })
+    }
+}
+
+impl std::fmt::Display for TopicsDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "  Topics:")?;
+        for topic in &self.additions {
+            writeln!(f, "    Adding '{topic}'")?;
+        }
+        for topic in &self.removals {
+            writeln!(f, "    Removing '{topic}'")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(serde::Serialize)]
 struct RepoPermissionAssignmentDiff {
     collaborator: RepoCollaborator,
     diff: RepoPermissionDiff,
 }
 
 impl RepoPermissionAssignmentDiff {
+    fn context(&self, org: &str, repo_name: &str) -> String {
+        format!(
+            "updating the permissions of {} on repo {org}/{repo_name}",
+            self.collaborator
+        )
+    }
+
     fn apply(&self, sync: &GitHubWrite, org: &str, repo_name: &str) -> anyhow::Result<()> {
         match &self.diff {
             RepoPermissionDiff::Create(p) | RepoPermissionDiff::Update(_, p) => {
@@ -929,10 +1865,7 @@ impl RepoPermissionAssignmentDiff {
 
 impl std::fmt::Display for RepoPermissionAssignmentDiff {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let name = match &self.collaborator {
-            RepoCollaborator::Team(name) => format!("team '{name}'"),
-            RepoCollaborator::User(name) => format!("user '{name}'"),
-        };
+        let name = &self.collaborator;
         match &self.diff {
             RepoPermissionDiff::Create(p) => {
                 writeln!(f, "    Giving {name} {p} permission")
@@ -947,24 +1880,44 @@ impl std::fmt::Display for RepoPermissionAssignmentDiff {
     }
 }
 
+#[derive(serde::Serialize)]
+#[serde(rename_all = "snake_case")]
 enum RepoPermissionDiff {
     Create(RepoPermission),
     Update(RepoPermission, RepoPermission),
     Delete(RepoPermission),
 }
 
-#[derive(Clone)]
+#[derive(Clone, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
 enum RepoCollaborator {
     Team(String),
     User(String),
 }
 
+impl std::fmt::Display for RepoCollaborator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RepoCollaborator::Team(name) => write!(f, "team '{name}'"),
+            RepoCollaborator::User(name) => write!(f, "user '{name}'"),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
 struct BranchProtectionDiff {
     pattern: String,
     operation: BranchProtectionDiffOperation,
 }
 
 impl BranchProtectionDiff {
+    fn context(&self, org: &str, repo_name: &str) -> String {
+        format!(
+            "updating branch protection '{}' on repo {org}/{repo_name}",
+            self.pattern
+        )
+    }
+
     fn apply(
         &self,
         sync: &GitHubWrite,
@@ -1016,6 +1969,118 @@ impl std::fmt::Display for BranchProtectionDiff {
     }
 }
 
+#[derive(serde::Serialize)]
+struct RulesetDiff {
+    name: String,
+    operation: RulesetDiffOperation,
+}
+
+impl RulesetDiff {
+    fn context(&self, org: &str, repo_name: &str) -> String {
+        let action = match self.operation {
+            RulesetDiffOperation::Create(_) => "creating",
+            RulesetDiffOperation::Update(..) => "updating",
+            RulesetDiffOperation::Delete(_) => "deleting",
+        };
+        format!("{action} ruleset '{}' on repo {org}/{repo_name}", self.name)
+    }
+
+    fn apply(&self, sync: &GitHubWrite, org: &str, repo_name: &str) -> anyhow::Result<()> {
+        match &self.operation {
+            RulesetDiffOperation::Create(ruleset) => sync.create_ruleset(org, repo_name, ruleset),
+            RulesetDiffOperation::Update(id, _, ruleset) => {
+                sync.update_ruleset(org, repo_name, *id, ruleset)
+            }
+            RulesetDiffOperation::Delete(id) => sync.delete_ruleset(org, repo_name, *id),
+        }
+    }
+}
+
+impl std::fmt::Display for RulesetDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "      {}", self.name)?;
+        match &self.operation {
+            RulesetDiffOperation::Create(ruleset) => log_ruleset(ruleset, None, f),
+            RulesetDiffOperation::Update(_, old, new) => log_ruleset(old, Some(new), f),
+            RulesetDiffOperation::Delete(_) => writeln!(f, "        Deleting ruleset"),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum RulesetDiffOperation {
+    Create(Ruleset),
+    Update(u64, Ruleset, Ruleset),
+    Delete(u64),
+}
+
+fn log_ruleset(
+    current: &Ruleset,
+    new: Option<&Ruleset>,
+    mut result: impl Write,
+) -> std::fmt::Result {
+    macro_rules! log {
+        ($str:literal, $($field:ident).+) => {
+            let old = &current.$($field).+;
+            if let Some(new) = new.map(|n| &n.$($field).+) {
+                if old != new {
+                    writeln!(result, "        {}: {:?} => {:?}", $str, old, new)?;
+                }
+            } else {
+                writeln!(result, "        {}: {:?}", $str, old)?;
+            }
+        };
+    }
+
+    log!("Target", target);
+    log!("Enforcement", enforcement);
+    log!("Included Refs", conditions.ref_name.include);
+    log!("Excluded Refs", conditions.ref_name.exclude);
+    log!("Bypass Actors", bypass_actors);
+    log!("Rules", rules);
+    Ok(())
+}
+
+/// Log the configured options of a repo, or the ones that changed if `new` is passed.
+fn log_repo_options(
+    current: &api::RepoOptions,
+    new: Option<&api::RepoOptions>,
+    mut result: impl Write,
+) -> std::fmt::Result {
+    macro_rules! log {
+        ($str:literal, $field:ident) => {
+            let old = current.$field.map(|v| v.to_string());
+            match new.map(|n| n.$field.map(|v| v.to_string())) {
+                None => {
+                    if let Some(old) = old {
+                        writeln!(result, "  {}: {}", $str, old)?;
+                    }
+                }
+                Some(new) if new != old => writeln!(
+                    result,
+                    "  {}: {} => {}",
+                    $str,
+                    old.as_deref().unwrap_or("unknown"),
+                    new.as_deref().unwrap_or("unknown")
+                )?,
+                Some(_) => {}
+            }
+        };
+    }
+
+    log!("Allow merge commits", allow_merge_commit);
+    log!("Allow squash merging", allow_squash_merge);
+    log!("Allow rebase merging", allow_rebase_merge);
+    log!("Delete branches on merge", delete_branch_on_merge);
+    log!("Issues", has_issues);
+    log!("Wiki", has_wiki);
+    log!("Projects", has_projects);
+    log!("Discussions", has_discussions);
+    log!("Visibility", visibility);
+    Ok(())
+}
+
 fn log_branch_protection(
     current: &api::BranchProtection,
     new: Option<&api::BranchProtection>,
@@ -1043,23 +2108,43 @@ fn log_branch_protection(
         "Required Approving Review Count",
         required_approving_review_count
     );
-    log!("Required Checks", required_status_check_contexts);
+    log!("Required Checks", required_status_checks);
+    log!("Strict Required Checks", requires_strict_status_checks);
+    log!("Admin Enforced", is_admin_enforced);
+    log!("Code Owner Reviews", requires_code_owner_reviews);
+    log!("Linear History", requires_linear_history);
+    log!("Conversation Resolution", requires_conversation_resolution);
+    log!("Allow Force Pushes", allows_force_pushes);
+    log!("Allow Deletions", allows_deletions);
     log!("Allowances", push_allowances);
+    log!("Review Dismissal Allowances", review_dismissal_allowances);
     Ok(())
 }
 
+#[derive(serde::Serialize)]
+#[serde(rename_all = "snake_case")]
 enum BranchProtectionDiffOperation {
     Create(api::BranchProtection),
     Update(String, api::BranchProtection, api::BranchProtection),
     Delete(String),
 }
 
+#[derive(serde::Serialize)]
+#[serde(rename_all = "snake_case")]
 enum AppInstallationDiff {
     Add(AppInstallation),
     Remove(AppInstallation),
 }
 
 impl AppInstallationDiff {
+    fn context(&self, org: &str, repo_name: &str) -> String {
+        let (AppInstallationDiff::Add(app) | AppInstallationDiff::Remove(app)) = self;
+        format!(
+            "updating the installation of app {} on repo {org}/{repo_name}",
+            app.app
+        )
+    }
+
     fn apply(&self, sync: &GitHubWrite, repo_id: u64) -> anyhow::Result<()> {
         match self {
             AppInstallationDiff::Add(app) => {
@@ -1086,7 +2171,66 @@ impl std::fmt::Display for AppInstallationDiff {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, serde::Serialize)]
+struct OrgMembershipDiff {
+    org: String,
+    member: String,
+    operation: OrgMembershipDiffOperation,
+}
+
+impl OrgMembershipDiff {
+    fn apply(&self, sync: &GitHubWrite) -> anyhow::Result<()> {
+        match self.operation {
+            OrgMembershipDiffOperation::Invite => sync.invite_org_member(&self.org, &self.member),
+            OrgMembershipDiffOperation::Remove => sync.remove_org_member(&self.org, &self.member),
+            OrgMembershipDiffOperation::Unmanaged => Ok(()),
+        }
+    }
+
+    fn context(&self) -> String {
+        match self.operation {
+            OrgMembershipDiffOperation::Invite => {
+                format!("inviting {} to org {}", self.member, self.org)
+            }
+            OrgMembershipDiffOperation::Remove => {
+                format!("removing {} from org {}", self.member, self.org)
+            }
+            OrgMembershipDiffOperation::Unmanaged => {
+                format!("reporting {} as unmanaged in org {}", self.member, self.org)
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for OrgMembershipDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (org, member) = (&self.org, &self.member);
+        match self.operation {
+            OrgMembershipDiffOperation::Invite => {
+                writeln!(f, "➕ Inviting '{member}' to org '{org}'")
+            }
+            OrgMembershipDiffOperation::Remove => {
+                writeln!(f, "❌ Removing '{member}' from org '{org}'")
+            }
+            OrgMembershipDiffOperation::Unmanaged => writeln!(
+                f,
+                "⚠️ '{member}' is a member of org '{org}' without being in any of its teams"
+            ),
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum OrgMembershipDiffOperation {
+    Invite,
+    Remove,
+    /// A member not in any team, reported but kept in the org.
+    Unmanaged,
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
 enum TeamDiff {
     Create(CreateTeamDiff),
     Edit(EditTeamDiff),
@@ -1094,15 +2238,25 @@ enum TeamDiff {
 }
 
 impl TeamDiff {
-    fn apply(self, sync: &GitHubWrite) -> anyhow::Result<()> {
+    fn apply(self, sync: &GitHubWrite, failures: &mut Failures) -> anyhow::Result<()> {
         match self {
-            TeamDiff::Create(c) => c.apply(sync)?,
-            TeamDiff::Edit(e) => e.apply(sync)?,
-            TeamDiff::Delete(d) => d.apply(sync)?,
+            TeamDiff::Create(c) => c.apply(sync, failures)?,
+            TeamDiff::Edit(e) => e.apply(sync, failures)?,
+            TeamDiff::Delete(d) => {
+                let context = format!("deleting team {}/{}", d.org, d.name);
+                failures.handle(d.apply(sync), || context)?;
+            }
         }
 
         Ok(())
     }
+
+    fn noop(&self) -> bool {
+        match self {
+            TeamDiff::Create(_) | TeamDiff::Delete(_) => false,
+            TeamDiff::Edit(e) => e.noop(),
+        }
+    }
 }
 
 impl std::fmt::Display for TeamDiff {
@@ -1115,20 +2269,34 @@ impl std::fmt::Display for TeamDiff {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, serde::Serialize)]
 struct CreateTeamDiff {
     org: String,
     name: String,
     description: String,
     privacy: TeamPrivacy,
+    parent: Option<String>,
     members: Vec<(String, TeamRole)>,
 }
 
 impl CreateTeamDiff {
-    fn apply(self, sync: &GitHubWrite) -> anyhow::Result<()> {
-        sync.create_team(&self.org, &self.name, &self.description, self.privacy)?;
+    fn apply(self, sync: &GitHubWrite, failures: &mut Failures) -> anyhow::Result<()> {
+        let (org, name) = (&self.org, &self.name);
+        let created = sync.create_team(
+            org,
+            name,
+            &self.description,
+            self.privacy,
+            self.parent.as_deref(),
+        );
+        if failures
+            .handle(created, || format!("creating team {org}/{name}"))?
+            .is_none()
+        {
+            return Ok(());
+        }
         for (member_name, role) in self.members {
-            MemberDiff::Create(role).apply(&self.org, &self.name, &member_name, sync)?;
+            MemberDiff::Create(role).apply(org, name, &member_name, sync, failures)?;
         }
 
         Ok(())
@@ -1149,6 +2317,9 @@ impl std::fmt::Display for CreateTeamDiff {
                 TeamPrivacy::Closed => "closed",
             }
         )?;
+        if let Some(parent) = &self.parent {
+            writeln!(f, "  Parent: {parent}")?;
+        }
         writeln!(f, "  Members:")?;
         for (name, role) in &self.members {
             writeln!(f, "    {name}: {role}")?;
@@ -1157,33 +2328,38 @@ impl std::fmt::Display for CreateTeamDiff {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, serde::Serialize)]
 struct EditTeamDiff {
     org: String,
     name: String,
     name_diff: Option<String>,
     description_diff: Option<(String, String)>,
     privacy_diff: Option<(TeamPrivacy, TeamPrivacy)>,
+    parent_diff: Option<(Option<String>, String)>,
     member_diffs: Vec<(String, MemberDiff)>,
 }
 
 impl EditTeamDiff {
-    fn apply(self, sync: &GitHubWrite) -> anyhow::Result<()> {
+    fn apply(self, sync: &GitHubWrite, failures: &mut Failures) -> anyhow::Result<()> {
+        let (org, name) = (&self.org, &self.name);
         if self.name_diff.is_some()
             || self.description_diff.is_some()
             || self.privacy_diff.is_some()
+            || self.parent_diff.is_some()
         {
-            sync.edit_team(
-                &self.org,
-                &self.name,
+            let result = sync.edit_team(
+                org,
+                name,
                 self.name_diff.as_deref(),
                 self.description_diff.as_ref().map(|(_, d)| d.as_str()),
                 self.privacy_diff.map(|(_, p)| p),
-            )?;
+                self.parent_diff.as_ref().map(|(_, p)| Some(p.as_str())),
+            );
+            failures.handle(result, || format!("editing team {org}/{name}"))?;
         }
 
         for (member_name, member_diff) in self.member_diffs {
-            member_diff.apply(&self.org, &self.name, &member_name, sync)?;
+            member_diff.apply(org, name, &member_name, sync, failures)?;
         }
 
         Ok(())
@@ -1193,6 +2369,7 @@ impl EditTeamDiff {
         self.name_diff.is_none()
             && self.description_diff.is_none()
             && self.privacy_diff.is_none()
+            && self.parent_diff.is_none()
             && self.member_diffs.iter().all(|(_, d)| d.is_noop())
     }
 }
@@ -1216,6 +2393,13 @@ impl std::fmt::Display for EditTeamDiff {
             };
             writeln!(f, "  New privacy: '{}' => '{}'", display(old), display(new))?;
         }
+        if let Some((old, new)) = &self.parent_diff {
+            writeln!(
+                f,
+                "  New parent: '{}' => '{new}'",
+                old.as_deref().unwrap_or("none")
+            )?;
+        }
         for (member, diff) in &self.member_diffs {
             match diff {
                 MemberDiff::Create(r) => {
@@ -1234,7 +2418,8 @@ impl std::fmt::Display for EditTeamDiff {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
 enum MemberDiff {
     Create(TeamRole),
     ChangeRole((TeamRole, TeamRole)),
@@ -1243,14 +2428,24 @@ enum MemberDiff {
 }
 
 impl MemberDiff {
-    fn apply(self, org: &str, team: &str, member: &str, sync: &GitHubWrite) -> anyhow::Result<()> {
-        match self {
+    fn apply(
+        self,
+        org: &str,
+        team: &str,
+        member: &str,
+        sync: &GitHubWrite,
+        failures: &mut Failures,
+    ) -> anyhow::Result<()> {
+        let result = match self {
             MemberDiff::Create(role) | MemberDiff::ChangeRole((_, role)) => {
-                sync.set_team_membership(org, team, member, role)?;
+                sync.set_team_membership(org, team, member, role)
             }
-            MemberDiff::Delete => sync.remove_team_membership(org, team, member)?,
-            MemberDiff::Noop => {}
-        }
+            MemberDiff::Delete => sync.remove_team_membership(org, team, member),
+            MemberDiff::Noop => Ok(()),
+        };
+        failures.handle(result, || {
+            format!("updating the membership of {member} in team {org}/{team}")
+        })?;
 
         Ok(())
     }
@@ -1260,7 +2455,7 @@ impl MemberDiff {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, serde::Serialize)]
 struct DeleteTeamDiff {
     org: String,
     name: String,
@@ -1,4 +1,20 @@
-use crate::github::tests::test_utils::{DataModel, TeamData};
+use crate::config::{
+    BotConfig, BranchProtectionConfig, GitHubAppConfig, GitHubConfig, OrgConfig,
+    OrgMembershipConfig, RepoConfig, RepoOptionsConfig, TeamPermissionPolicy,
+};
+use crate::failures::Failures;
+use crate::github::api::{
+    PushAllowanceActor, RepoUser, RepoVisibility, RequiredStatusCheck, UserPushAllowanceActor,
+};
+use crate::github::tests::test_utils::{DataModel, RepoData, TeamData};
+use crate::github::{
+    normalize_topics, Diff, GitHubWrite, HttpClient, RepoDiff, RepoOptions, RepoPermission,
+    Ruleset, TeamDiff, TopicsDiff,
+};
+use crate::mock_server::{MockResponse, MockServer};
+use crate::retry::RetryPolicy;
+use rust_team_data::v1;
+use std::collections::{BTreeMap, HashMap};
 
 mod test_utils;
 
@@ -26,6 +42,7 @@ fn team_create() {
                 name: "admins-gh",
                 description: "Managed by the rust-lang/team repository.",
                 privacy: Closed,
+                parent: None,
                 members: [
                     (
                         "mark",
@@ -61,6 +78,7 @@ fn team_add_member() {
                 name_diff: None,
                 description_diff: None,
                 privacy_diff: None,
+                parent_diff: None,
                 member_diffs: [
                     (
                         "mark",
@@ -100,6 +118,7 @@ fn team_dont_add_member_if_invitation_is_pending() {
                 name_diff: None,
                 description_diff: None,
                 privacy_diff: None,
+                parent_diff: None,
                 member_diffs: [
                     (
                         "mark",
@@ -138,6 +157,7 @@ fn team_remove_member() {
                 name_diff: None,
                 description_diff: None,
                 privacy_diff: None,
+                parent_diff: None,
                 member_diffs: [
                     (
                         "mark",
@@ -180,6 +200,7 @@ fn team_delete() {
                 name_diff: None,
                 description_diff: None,
                 privacy_diff: None,
+                parent_diff: None,
                 member_diffs: [
                     (
                         "mark",
@@ -198,3 +219,987 @@ fn team_delete() {
     ]
     "###);
 }
+
+#[test]
+fn team_create_nested() {
+    let mut model = DataModel::default();
+    let user = model.create_user("mark");
+    let gh = model.gh_model();
+    model.create_team(
+        TeamData::new("wg-compiler-perf")
+            .subteam_of(Some("compiler".to_string()))
+            .gh_team("wg-compiler-perf-gh", &[user]),
+    );
+    model.create_team(TeamData::new("compiler").gh_team("compiler-gh", &[user]));
+    let team_diff = model.diff_teams(gh);
+    insta::assert_debug_snapshot!(team_diff, @r###"
+    [
+        Create(
+            CreateTeamDiff {
+                org: "rust-lang",
+                name: "compiler-gh",
+                description: "Managed by the rust-lang/team repository.",
+                privacy: Closed,
+                parent: None,
+                members: [
+                    (
+                        "mark",
+                        Member,
+                    ),
+                ],
+            },
+        ),
+        Create(
+            CreateTeamDiff {
+                org: "rust-lang",
+                name: "wg-compiler-perf-gh",
+                description: "Managed by the rust-lang/team repository.",
+                privacy: Closed,
+                parent: Some(
+                    "compiler-gh",
+                ),
+                members: [
+                    (
+                        "mark",
+                        Member,
+                    ),
+                ],
+            },
+        ),
+    ]
+    "###);
+}
+
+#[test]
+fn team_change_parent() {
+    let mut model = DataModel::default();
+    let user = model.create_user("mark");
+    model.create_team(TeamData::new("compiler").gh_team("compiler-gh", &[user]));
+    model.create_team(TeamData::new("wg-compiler-perf").gh_team("wg-compiler-perf-gh", &[user]));
+    let gh = model.gh_model();
+
+    model.get_team("wg-compiler-perf").set_parent("compiler");
+    let team_diff = model.diff_teams(gh);
+    insta::assert_debug_snapshot!(team_diff, @r###"
+    [
+        Edit(
+            EditTeamDiff {
+                org: "rust-lang",
+                name: "compiler-gh",
+                name_diff: None,
+                description_diff: None,
+                privacy_diff: None,
+                parent_diff: None,
+                member_diffs: [
+                    (
+                        "mark",
+                        Noop,
+                    ),
+                ],
+            },
+        ),
+        Edit(
+            EditTeamDiff {
+                org: "rust-lang",
+                name: "wg-compiler-perf-gh",
+                name_diff: None,
+                description_diff: None,
+                privacy_diff: None,
+                parent_diff: Some(
+                    (
+                        None,
+                        "compiler-gh",
+                    ),
+                ),
+                member_diffs: [
+                    (
+                        "mark",
+                        Noop,
+                    ),
+                ],
+            },
+        ),
+    ]
+    "###);
+}
+
+#[test]
+fn team_keep_parent_set_on_github() {
+    let mut model = DataModel::default();
+    let user = model.create_user("mark");
+    model.create_team(TeamData::new("compiler").gh_team("compiler-gh", &[user]));
+    model.create_team(TeamData::new("wg-compiler-perf").gh_team("wg-compiler-perf-gh", &[user]));
+    let mut gh = model.gh_model();
+    gh.set_team_parent("wg-compiler-perf-gh", "compiler-gh");
+
+    let team_diff = model.diff_teams(gh);
+    assert!(team_diff.iter().all(|diff| diff.noop()));
+}
+
+#[test]
+fn team_description_from_team_data() {
+    let mut model = DataModel::default();
+    let user = model.create_user("mark");
+    model.create_team(TeamData::new("security").gh_team("security-gh", &[user]));
+    let gh = model.gh_model();
+
+    model.create_team(
+        TeamData::new("infra")
+            .description("Infrastructure team".to_string())
+            .gh_team("infra-gh", &[user]),
+    );
+    model
+        .get_team("security")
+        .set_description("Security response team");
+    let team_diff = model.diff_teams(gh);
+    insta::assert_debug_snapshot!(team_diff, @r###"
+    [
+        Edit(
+            EditTeamDiff {
+                org: "rust-lang",
+                name: "security-gh",
+                name_diff: None,
+                description_diff: Some(
+                    (
+                        "Managed by the rust-lang/team repository.",
+                        "Security response team",
+                    ),
+                ),
+                privacy_diff: None,
+                parent_diff: None,
+                member_diffs: [
+                    (
+                        "mark",
+                        Noop,
+                    ),
+                ],
+            },
+        ),
+        Create(
+            CreateTeamDiff {
+                org: "rust-lang",
+                name: "infra-gh",
+                description: "Infrastructure team",
+                privacy: Closed,
+                parent: None,
+                members: [
+                    (
+                        "mark",
+                        Member,
+                    ),
+                ],
+            },
+        ),
+    ]
+    "###);
+}
+
+#[test]
+fn team_unknown_github_user() {
+    let mut model = DataModel::default();
+    model.create_user("mark");
+    let gh = model.gh_model();
+
+    let ghost = model.create_user("ghost");
+    model.create_team(TeamData::new("infra").gh_team("infra-gh", &[ghost]));
+    let err = model
+        .diff_teams_with_config(gh, GitHubConfig::default())
+        .unwrap_err();
+    assert_eq!(err.to_string(), "GitHub user with ID 1 not found");
+}
+
+#[test]
+fn team_diff_json() {
+    let mut model = DataModel::default();
+    let user = model.create_user("mark");
+    let user2 = model.create_user("jan");
+    model.create_team(TeamData::new("admins").gh_team("admins-gh", &[user]));
+    let gh = model.gh_model();
+
+    model.get_team("admins").add_gh_member("admins-gh", user2);
+    let team_diff = model.diff_teams(gh);
+    insta::assert_snapshot!(serde_json::to_string_pretty(&team_diff).unwrap(), @r###"
+    [
+      {
+        "edit": {
+          "org": "rust-lang",
+          "name": "admins-gh",
+          "name_diff": null,
+          "description_diff": null,
+          "privacy_diff": null,
+          "parent_diff": null,
+          "member_diffs": [
+            [
+              "mark",
+              "noop"
+            ],
+            [
+              "jan",
+              {
+                "create": "member"
+              }
+            ]
+          ]
+        }
+      }
+    ]
+    "###);
+}
+
+fn org_membership_config(remove_unmanaged: bool, allowed_members: &[&str]) -> GitHubConfig {
+    let mut config = GitHubConfig::default();
+    config.org_membership.insert(
+        "rust-lang".to_string(),
+        OrgMembershipConfig {
+            remove_unmanaged,
+            allowed_members: allowed_members.iter().map(|m| m.to_string()).collect(),
+        },
+    );
+    config
+}
+
+#[test]
+fn org_membership_invite_missing_members() {
+    let mut model = DataModel::default();
+    let user = model.create_user("mark");
+    let user2 = model.create_user("jan");
+    let user3 = model.create_user("ana");
+    model.create_team(TeamData::new("admins").gh_team("admins-gh", &[user]));
+    let mut gh = model.gh_model();
+    gh.add_org_invitation("Ana");
+
+    model.get_team("admins").add_gh_member("admins-gh", user2);
+    model.get_team("admins").add_gh_member("admins-gh", user3);
+    let diff = model.diff_org_memberships(gh, org_membership_config(false, &[]));
+    insta::assert_debug_snapshot!(diff, @r###"
+    [
+        OrgMembershipDiff {
+            org: "rust-lang",
+            member: "jan",
+            operation: Invite,
+        },
+    ]
+    "###);
+}
+
+#[test]
+fn org_membership_unmanaged_members() {
+    let mut model = DataModel::default();
+    let user = model.create_user("mark");
+    let owner = model.create_user("owner");
+    let bot = model.create_user("bot");
+    let user2 = model.create_user("jan");
+    model.create_team(TeamData::new("admins").gh_team("admins-gh", &[user, user2]));
+    let mut gh = model.gh_model();
+    gh.add_owner(owner);
+    gh.add_org_member(bot);
+
+    model
+        .get_team("admins")
+        .remove_gh_member("admins-gh", user2);
+    let flagged = model.diff_org_memberships(gh.clone(), org_membership_config(false, &["BOT"]));
+    insta::assert_debug_snapshot!(flagged, @r###"
+    [
+        OrgMembershipDiff {
+            org: "rust-lang",
+            member: "jan",
+            operation: Unmanaged,
+        },
+    ]
+    "###);
+    // Unmanaged members are part of the JSON plan too, even though nothing is applied.
+    insta::assert_snapshot!(serde_json::to_string(&flagged).unwrap(), @r###"
+    [{"org":"rust-lang","member":"jan","operation":"unmanaged"}]
+    "###);
+    let removed = model.diff_org_memberships(gh, org_membership_config(true, &[]));
+    insta::assert_debug_snapshot!(removed, @r###"
+    [
+        OrgMembershipDiff {
+            org: "rust-lang",
+            member: "bot",
+            operation: Remove,
+        },
+        OrgMembershipDiff {
+            org: "rust-lang",
+            member: "jan",
+            operation: Remove,
+        },
+    ]
+    "###);
+}
+
+fn repo_config(repo: &str, config: RepoConfig) -> GitHubConfig {
+    GitHubConfig {
+        repos: HashMap::from([(format!("rust-lang/{repo}"), config)]),
+        ..GitHubConfig::default()
+    }
+}
+
+fn repo_plan(diffs: &[RepoDiff]) -> String {
+    diffs.iter().map(|diff| diff.to_string()).collect()
+}
+
+#[test]
+fn repo_noop() {
+    let mut model = DataModel::default();
+    model.create_repo(RepoData::new("rust").team("compiler", v1::RepoPermission::Write));
+    let gh = model.gh_model();
+    let diffs = model.diff_repos(gh, GitHubConfig::default()).unwrap();
+    assert_eq!(repo_plan(&diffs), "");
+}
+
+#[test]
+fn repo_edit_options() {
+    let mut model = DataModel::default();
+    model.create_repo(RepoData::new("rust"));
+    let mut gh = model.gh_model();
+    gh.set_repo_options(
+        "rust",
+        RepoOptions {
+            allow_merge_commit: Some(true),
+            has_wiki: Some(true),
+            visibility: Some(RepoVisibility::Public),
+            ..RepoOptions::default()
+        },
+    );
+    let config = RepoConfig {
+        options: RepoOptionsConfig {
+            allow_merge_commit: Some(true),
+            has_wiki: Some(false),
+            ..RepoOptionsConfig::default()
+        },
+        ..RepoConfig::default()
+    };
+    let diffs = model.diff_repos(gh, repo_config("rust", config)).unwrap();
+    insta::assert_snapshot!(repo_plan(&diffs), @r###"
+    📝 Editing repo 'rust-lang/rust':
+      Wiki: true => false
+    "###);
+}
+
+#[test]
+fn repo_visibility_from_team_data() {
+    let mut model = DataModel::default();
+    model.create_repo(RepoData::new("rust"));
+    model.create_repo(RepoData::new("security").private(true));
+    model.create_repo(RepoData::new("website"));
+    let mut gh = model.gh_model();
+    gh.add_repo("website").repo.options.visibility = Some(RepoVisibility::Private);
+
+    let diffs = model.diff_repos(gh, GitHubConfig::default()).unwrap();
+    insta::assert_snapshot!(repo_plan(&diffs), @r###"
+    📝 Editing repo 'rust-lang/website':
+      Visibility: private => public
+    "###);
+}
+
+#[test]
+fn repo_disable_all_merge_methods_with_current_options() {
+    let mut model = DataModel::default();
+    model.create_repo(RepoData::new("rust"));
+    let mut gh = model.gh_model();
+    gh.set_repo_options(
+        "rust",
+        RepoOptions {
+            allow_merge_commit: Some(false),
+            allow_squash_merge: Some(false),
+            allow_rebase_merge: Some(true),
+            ..RepoOptions::default()
+        },
+    );
+    let config = RepoConfig {
+        options: RepoOptionsConfig {
+            allow_rebase_merge: Some(false),
+            ..RepoOptionsConfig::default()
+        },
+        ..RepoConfig::default()
+    };
+    let err = model
+        .diff_repos(gh, repo_config("rust", config))
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.to_string(),
+        "repo rust-lang/rust must allow at least one merge method"
+    );
+}
+
+#[test]
+fn repo_update_topics() {
+    let mut model = DataModel::default();
+    model.create_repo(RepoData::new("rust"));
+    model.create_repo(RepoData::new("cargo"));
+    let mut gh = model.gh_model();
+    gh.set_repo_topics("rust", &["rust", "compiler"]);
+    gh.set_repo_topics("cargo", &["rust", "cargo"]);
+    let mut config = repo_config(
+        "rust",
+        RepoConfig {
+            topics: Some(vec!["Rust".to_string(), "language".to_string()]),
+            ..RepoConfig::default()
+        },
+    );
+    // Topics are compared case-insensitively.
+    config.repos.insert(
+        "rust-lang/cargo".to_string(),
+        RepoConfig {
+            topics: Some(vec!["Cargo".to_string(), "rust".to_string()]),
+            ..RepoConfig::default()
+        },
+    );
+    let diffs = model.diff_repos(gh, config).unwrap();
+    insta::assert_snapshot!(repo_plan(&diffs), @r###"
+    📝 Editing repo 'rust-lang/rust':
+      Topics:
+        Adding 'language'
+        Removing 'compiler'
+    "###);
+}
+
+#[test]
+fn repo_keep_unconfigured_topics() {
+    let mut model = DataModel::default();
+    model.create_repo(RepoData::new("rust"));
+    let mut gh = model.gh_model();
+    gh.set_repo_topics("rust", &["rust", "compiler"]);
+    let diffs = model.diff_repos(gh, GitHubConfig::default()).unwrap();
+    assert_eq!(repo_plan(&diffs), "");
+}
+
+fn ruleset(json: &str) -> Ruleset {
+    serde_json::from_str(json).unwrap()
+}
+
+#[test]
+fn repo_diff_rulesets() {
+    let mut model = DataModel::default();
+    model.create_repo(RepoData::new("rust"));
+    let mut gh = model.gh_model();
+    gh.add_ruleset(
+        "rust",
+        1,
+        ruleset(r#"{"name": "main", "rules": [{"type": "deletion"}]}"#),
+    );
+    gh.add_ruleset(
+        "rust",
+        2,
+        ruleset(r#"{"name": "unchanged", "target": "tag"}"#),
+    );
+    gh.add_ruleset("rust", 3, ruleset(r#"{"name": "old"}"#));
+    let config = RepoConfig {
+        rulesets: Some(vec![
+            ruleset(
+                r#"{"name": "main", "rules": [{"type": "non_fast_forward"}, {"type": "deletion"}]}"#,
+            ),
+            ruleset(r#"{"name": "unchanged", "target": "tag"}"#),
+            ruleset(r#"{"name": "new", "enforcement": "disabled"}"#),
+        ]),
+        ..RepoConfig::default()
+    };
+    let repo_diffs = model.diff_repos(gh, repo_config("rust", config)).unwrap();
+    insta::assert_snapshot!(repo_plan(&repo_diffs), @r###"
+    📝 Editing repo 'rust-lang/rust':
+      Rulesets:
+          main
+            Rules: [Deletion] => [Deletion, NonFastForward]
+          new
+            Target: Branch
+            Enforcement: Disabled
+            Included Refs: []
+            Excluded Refs: []
+            Bypass Actors: []
+            Rules: []
+          old
+            Deleting ruleset
+    "###);
+
+    let diff = Diff {
+        org_membership_diffs: vec![],
+        team_diffs: vec![],
+        repo_diffs,
+        unmanaged_repos: vec![],
+    };
+    assert_eq!(
+        diff.removals().repo_setting_deletions,
+        ["ruleset 'old' of rust-lang/rust"]
+    );
+}
+
+/// Diff of a repo whose branch protection is replaced by a new ruleset.
+fn replace_branch_protection_diff() -> Vec<RepoDiff> {
+    let mut model = DataModel::default();
+    model.create_repo(RepoData::new("rust"));
+    let mut gh = model.gh_model();
+    gh.add_branch_protection("rust", "BPR_1", "master");
+    let config = RepoConfig {
+        rulesets: Some(vec![ruleset(r#"{"name": "main"}"#)]),
+        replace_branch_protections: true,
+        ..RepoConfig::default()
+    };
+    model.diff_repos(gh, repo_config("rust", config)).unwrap()
+}
+
+fn apply_repo_diffs(diffs: Vec<RepoDiff>, responses: Vec<MockResponse>) -> (MockServer, Failures) {
+    let server = MockServer::start(responses);
+    let client = HttpClient::from_url_and_token(
+        server.url().to_string(),
+        "token".to_string(),
+        RetryPolicy::default(),
+    )
+    .unwrap();
+    let write = GitHubWrite::new(client, false).unwrap();
+    let mut failures = Failures::new(true);
+    for diff in diffs {
+        diff.apply(&write, &mut failures).unwrap();
+    }
+    (server, failures)
+}
+
+#[test]
+fn repo_replace_branch_protections_with_rulesets() {
+    let diffs = replace_branch_protection_diff();
+    insta::assert_snapshot!(repo_plan(&diffs), @r###"
+    📝 Editing repo 'rust-lang/rust':
+      Rulesets:
+          main
+            Target: Branch
+            Enforcement: Active
+            Included Refs: []
+            Excluded Refs: []
+            Bypass Actors: []
+            Rules: []
+      Branch Protections:
+          master
+            Deleting branch protection
+    "###);
+
+    let (server, failures) = apply_repo_diffs(
+        diffs,
+        vec![
+            MockResponse::new(201, "{}"),
+            MockResponse::new(200, r#"{"data": {}}"#),
+        ],
+    );
+    failures.into_result().unwrap();
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].path, "/repos/rust-lang/rust/rulesets");
+    assert_eq!(requests[1].path, "/graphql");
+    assert!(requests[1].body.contains("deleteBranchProtectionRule"));
+}
+
+#[test]
+fn repo_keep_branch_protections_when_ruleset_fails() {
+    let (server, failures) = apply_repo_diffs(
+        replace_branch_protection_diff(),
+        vec![MockResponse::new(
+            422,
+            r#"{"message": "Validation Failed"}"#,
+        )],
+    );
+    let err = failures.into_result().unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("creating ruleset 'main' on repo rust-lang/rust"),
+        "{err}"
+    );
+    // The branch protection isn't deleted, as the ruleset replacing it is missing.
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].path, "/repos/rust-lang/rust/rulesets");
+}
+
+#[test]
+fn repo_branch_protection_settings() {
+    let mut model = DataModel::default();
+    model.create_repo(
+        RepoData::new("rust").branch_protections(vec![v1::BranchProtection {
+            pattern: "master".to_string(),
+            dismiss_stale_review: false,
+            mode: v1::BranchProtectionMode::PrRequired {
+                ci_checks: vec!["ci".to_string()],
+                required_approvals: 0,
+            },
+            allowed_merge_teams: vec![],
+        }]),
+    );
+    let mut gh = model.gh_model();
+    let protection = gh.add_branch_protection("rust", "BPR_1", "master");
+    protection.required_status_checks = vec![RequiredStatusCheck {
+        context: "ci".to_string(),
+        app_id: None,
+    }];
+    // Enabled by hand and not configured, so it's left untouched.
+    protection.requires_linear_history = true;
+    protection.allows_deletions = true;
+
+    let config = RepoConfig {
+        branch_protections: HashMap::from([(
+            "master".to_string(),
+            BranchProtectionConfig {
+                status_checks_app_id: Some(15368),
+                requires_strict_status_checks: Some(true),
+                allows_deletions: Some(false),
+                review_dismissal_teams: Some(vec!["infra".to_string()]),
+                ..BranchProtectionConfig::default()
+            },
+        )]),
+        ..RepoConfig::default()
+    };
+    let diffs = model.diff_repos(gh, repo_config("rust", config)).unwrap();
+    insta::assert_snapshot!(repo_plan(&diffs), @r###"
+    📝 Editing repo 'rust-lang/rust':
+      Branch Protections:
+          master
+            Required Checks: [RequiredStatusCheck { context: "ci", app_id: None }] => [RequiredStatusCheck { context: "ci", app_id: Some(15368) }]
+            Strict Required Checks: false => true
+            Allow Deletions: true => false
+            Review Dismissal Allowances: [] => [Team(TeamPushAllowanceActor { organization: Login { login: "rust-lang" }, name: "infra" })]
+    "###);
+}
+
+#[test]
+fn repo_branch_protection_bors_user() {
+    let mut model = DataModel::default();
+    model.create_repo(
+        RepoData::new("rust")
+            .bots(vec![v1::Bot::Bors])
+            .branch_protections(vec![v1::BranchProtection {
+                pattern: "master".to_string(),
+                dismiss_stale_review: false,
+                mode: v1::BranchProtectionMode::PrRequired {
+                    ci_checks: vec![],
+                    required_approvals: 1,
+                },
+                allowed_merge_teams: vec![],
+            }]),
+    );
+    let mut gh = model.gh_model();
+    gh.add_repo("rust").collaborators.push(RepoUser {
+        name: "bors-ng".to_string(),
+        permission: RepoPermission::Write,
+    });
+    gh.add_branch_protection("rust", "BPR_1", "master")
+        .push_allowances = vec![PushAllowanceActor::User(UserPushAllowanceActor {
+        login: "bors".to_string(),
+    })];
+
+    let config = GitHubConfig {
+        bots: vec![BotConfig {
+            bot: v1::Bot::Bors,
+            user: "bors-ng".to_string(),
+            permission: RepoPermission::Write,
+        }],
+        ..GitHubConfig::default()
+    };
+    let diffs = model.diff_repos(gh, config).unwrap();
+    insta::assert_snapshot!(repo_plan(&diffs), @r###"
+    📝 Editing repo 'rust-lang/rust':
+      Branch Protections:
+          master
+            Allowances: [User(UserPushAllowanceActor { login: "bors" })] => [User(UserPushAllowanceActor { login: "bors-ng" })]
+    "###);
+}
+
+#[test]
+fn repo_app_installations() {
+    let mut model = DataModel::default();
+    let user = model.create_user("mark");
+    // The installations are only read in the orgs with teams.
+    model.create_team(TeamData::new("admins").gh_team("admins-gh", &[user]));
+    model.create_repo(RepoData::new("rust").bots(vec![v1::Bot::Renovate]));
+    model.create_repo(RepoData::new("cargo"));
+    model.create_repo(RepoData::new("old"));
+    model.create_repo(RepoData::new("installed").bots(vec![v1::Bot::Renovate]));
+    let mut gh = model.gh_model();
+    gh.add_app_installation(1, 2740, &["old", "installed"]);
+    gh.add_app_installation(2, 7, &[]);
+    // Installations of unknown apps are left untouched.
+    gh.add_app_installation(3, 99, &["rust", "old"]);
+
+    let mut config = repo_config(
+        "cargo",
+        RepoConfig {
+            apps: vec!["triagebot".to_string()],
+            ..RepoConfig::default()
+        },
+    );
+    config.apps = vec![
+        GitHubAppConfig {
+            name: "RenovateBot".to_string(),
+            app_id: 2740,
+            bot: Some(v1::Bot::Renovate),
+        },
+        GitHubAppConfig {
+            name: "triagebot".to_string(),
+            app_id: 7,
+            bot: None,
+        },
+    ];
+    let repo_diffs = model.diff_repos(gh, config).unwrap();
+    insta::assert_snapshot!(repo_plan(&repo_diffs), @r###"
+    📝 Editing repo 'rust-lang/rust':
+      App installation changes:
+        Install app RenovateBot
+    📝 Editing repo 'rust-lang/cargo':
+      App installation changes:
+        Install app triagebot
+    📝 Editing repo 'rust-lang/old':
+      App installation changes:
+        Remove app RenovateBot
+    "###);
+}
+
+fn unmanaged_repos_config(fail_on_new_unmanaged_repos: bool) -> GitHubConfig {
+    GitHubConfig {
+        orgs: HashMap::from([(
+            "rust-lang".to_string(),
+            OrgConfig {
+                report_unmanaged_repos: true,
+                known_unmanaged_repos: vec!["old-experiment".to_string()],
+                fail_on_new_unmanaged_repos,
+                ..OrgConfig::default()
+            },
+        )]),
+        ..GitHubConfig::default()
+    }
+}
+
+#[test]
+fn report_unmanaged_repos() {
+    let mut model = DataModel::default();
+    model.create_repo(RepoData::new("rust"));
+    let mut gh = model.gh_model();
+    // Known unmanaged repos are matched case-insensitively.
+    gh.add_repo("Old-Experiment").repo.archived = true;
+    let new = gh.add_repo("new-thing");
+    new.repo.options.visibility = Some(RepoVisibility::Private);
+    new.collaborators = vec![
+        RepoUser {
+            name: "mark".to_string(),
+            permission: RepoPermission::Admin,
+        },
+        RepoUser {
+            name: "jan".to_string(),
+            permission: RepoPermission::Write,
+        },
+    ];
+
+    let diff = model
+        .diff_all(gh.clone(), unmanaged_repos_config(false))
+        .unwrap();
+    insta::assert_snapshot!(diff.to_string(), @r###"
+    💻 Org Membership Diffs:
+    💻 Team Diffs:
+    💻 Repo Diffs:
+    💻 Unmanaged Repos:
+    ❔ 'rust-lang/Old-Experiment' (public) (archived)
+    🆕 'rust-lang/new-thing' (private)
+      Admins: mark
+    "###);
+    diff.check().unwrap();
+
+    // Unmanaged repos are only listed for the orgs opting into the report.
+    let diff = model.diff_all(gh, GitHubConfig::default()).unwrap();
+    assert!(diff.unmanaged_repos.is_empty());
+}
+
+#[test]
+fn fail_on_new_unmanaged_repos() {
+    let mut model = DataModel::default();
+    model.create_repo(RepoData::new("rust"));
+    let mut gh = model.gh_model();
+    gh.add_repo("old-experiment");
+    let diff = model
+        .diff_all(gh.clone(), unmanaged_repos_config(true))
+        .unwrap();
+    diff.check().unwrap();
+
+    gh.add_repo("new-thing");
+    let diff = model.diff_all(gh, unmanaged_repos_config(true)).unwrap();
+    assert_eq!(
+        diff.check().unwrap_err().to_string(),
+        "found new unmanaged repos: rust-lang/new-thing"
+    );
+}
+
+#[test]
+fn repo_topics_diff() {
+    let actual = vec!["rust".to_string(), "compiler".to_string()];
+    let expected = normalize_topics(&["Rust".to_string(), "language".to_string()]);
+    let diff = TopicsDiff::new(&actual, expected).unwrap();
+    assert_eq!(diff.topics, ["language", "rust"]);
+    assert_eq!(diff.additions, ["language"]);
+    assert_eq!(diff.removals, ["compiler"]);
+
+    let unchanged = normalize_topics(&["COMPILER".to_string(), "rust".to_string()]);
+    assert!(TopicsDiff::new(&actual, unchanged).is_none());
+}
+
+#[test]
+fn team_keep_bot_teams() {
+    let mut model = DataModel::default();
+    let user = model.create_user("mark");
+    model.create_team(
+        TeamData::new("admins")
+            .gh_team("admins-gh", &[user])
+            .gh_team("users-gh", &[user])
+            .gh_team("triagebot", &[user]),
+    );
+    let gh = model.gh_model();
+
+    model.get_team("admins").remove_gh_team("users-gh");
+    model.get_team("admins").remove_gh_team("triagebot");
+
+    let config = GitHubConfig {
+        bot_teams: vec!["triagebot".to_string()],
+        ..GitHubConfig::default()
+    };
+    let team_diff = model.diff_teams_with_config(gh, config).unwrap();
+    insta::assert_debug_snapshot!(team_diff, @r###"
+    [
+        Edit(
+            EditTeamDiff {
+                org: "rust-lang",
+                name: "admins-gh",
+                name_diff: None,
+                description_diff: None,
+                privacy_diff: None,
+                parent_diff: None,
+                member_diffs: [
+                    (
+                        "mark",
+                        Noop,
+                    ),
+                ],
+            },
+        ),
+        Delete(
+            DeleteTeamDiff {
+                org: "rust-lang",
+                name: "users-gh",
+                slug: "users-gh",
+            },
+        ),
+    ]
+    "###);
+}
+
+#[test]
+fn team_delete_following_org_policy() {
+    let mut model = DataModel::default();
+    let user = model.create_user("mark");
+    model.create_team(
+        TeamData::new("admins")
+            .gh_team("admins-gh", &[user])
+            .gh_team("users-gh", &[user])
+            .gh_team("wg-legacy", &[user]),
+    );
+    let gh = model.gh_model();
+    model.get_team("admins").remove_gh_team("users-gh");
+    model.get_team("admins").remove_gh_team("wg-legacy");
+
+    let deleted_teams = |orgs: HashMap<String, OrgConfig>| {
+        let config = GitHubConfig {
+            orgs,
+            ..GitHubConfig::default()
+        };
+        model
+            .diff_teams_with_config(gh.clone(), config)
+            .unwrap()
+            .into_iter()
+            .filter_map(|diff| match diff {
+                TeamDiff::Delete(delete) => Some(delete.name),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+    let policy = OrgConfig {
+        delete_unmanaged_teams: true,
+        ignored_teams: vec!["wg-legacy".to_string()],
+        ..OrgConfig::default()
+    };
+    assert_eq!(
+        deleted_teams(HashMap::from([("rust-lang".to_string(), policy)])),
+        ["users-gh"]
+    );
+    assert!(deleted_teams(HashMap::new()).is_empty());
+}
+
+fn security_policy_config() -> GitHubConfig {
+    GitHubConfig {
+        orgs: HashMap::from([(
+            "rust-lang".to_string(),
+            OrgConfig {
+                team_permissions: BTreeMap::from([(
+                    "security".to_string(),
+                    TeamPermissionPolicy {
+                        floor: Some(RepoPermission::Read),
+                        ceiling: Some(RepoPermission::Triage),
+                    },
+                )]),
+                ..OrgConfig::default()
+            },
+        )]),
+        ..GitHubConfig::default()
+    }
+}
+
+#[test]
+fn repo_permissions_downgrade_to_floor() {
+    let mut model = DataModel::default();
+    model.create_repo(RepoData::new("rust").team("compiler", v1::RepoPermission::Write));
+    let mut gh = model.gh_model();
+    gh.set_repo_team("rust", "security", RepoPermission::Admin);
+    gh.set_repo_team("rust", "wg-old", RepoPermission::Read);
+    let diffs = model.diff_repos(gh, security_policy_config()).unwrap();
+    insta::assert_snapshot!(repo_plan(&diffs), @r###"
+    📝 Editing repo 'rust-lang/rust':
+      Permission Changes:
+        Changing team 'security''s permission from admin to read
+        Removing team 'wg-old''s read permission 
+    "###);
+
+    let (server, failures) = apply_repo_diffs(
+        diffs,
+        vec![MockResponse::new(204, ""), MockResponse::new(204, "")],
+    );
+    failures.into_result().unwrap();
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].method, "PUT");
+    assert_eq!(
+        requests[0].path,
+        "/orgs/rust-lang/teams/security/repos/rust-lang/rust"
+    );
+    assert_eq!(requests[0].body, r#"{"permission":"pull"}"#);
+    assert_eq!(requests[1].method, "DELETE");
+    assert_eq!(
+        requests[1].path,
+        "/orgs/rust-lang/teams/wg-old/repos/rust-lang/rust"
+    );
+}
+
+#[test]
+fn repo_permissions_keep_floor() {
+    let mut model = DataModel::default();
+    model.create_repo(RepoData::new("rust"));
+    let mut gh = model.gh_model();
+    gh.set_repo_team("rust", "security", RepoPermission::Read);
+    let diffs = model.diff_repos(gh, security_policy_config()).unwrap();
+    assert_eq!(repo_plan(&diffs), "");
+}
+
+#[test]
+fn repo_permissions_clamp_to_ceiling() {
+    let mut model = DataModel::default();
+    model.create_repo(RepoData::new("rust").team("security", v1::RepoPermission::Write));
+    let mut gh = model.gh_model();
+    gh.set_repo_team("rust", "security", RepoPermission::Read);
+    let diffs = model.diff_repos(gh, security_policy_config()).unwrap();
+    insta::assert_snapshot!(repo_plan(&diffs), @r###"
+    📝 Editing repo 'rust-lang/rust':
+      Permission Changes:
+        Changing team 'security''s permission from read to triage
+    "###);
+}
@@ -1,13 +1,17 @@
 use std::collections::{HashMap, HashSet};
 
 use derive_builder::Builder;
-use rust_team_data::v1::{GitHubTeam, Person, TeamGitHub, TeamKind};
+use rust_team_data::v1::{self, GitHubTeam, Person, TeamGitHub, TeamKind, TeamWebsite};
 
+use crate::config::GitHubConfig;
 use crate::github::api::{
-    BranchProtection, GithubRead, OrgAppInstallation, Repo, RepoAppInstallation, RepoTeam,
-    RepoUser, Team, TeamMember, TeamPrivacy, TeamRole,
+    BranchProtection, GithubRead, OrgAppInstallation, Repo, RepoAppInstallation, RepoOptions,
+    RepoPermission, RepoState, RepoTeam, RepoUser, RepoVisibility, Ruleset, Team, TeamMember,
+    TeamPrivacy, TeamRole,
+};
+use crate::github::{
+    api, convert_permission, Diff, OrgMembershipDiff, RepoDiff, SyncGitHub, TeamDiff,
 };
-use crate::github::{api, SyncGitHub, TeamDiff};
 
 const DEFAULT_ORG: &str = "rust-lang";
 
@@ -22,6 +26,7 @@ type UserId = u64;
 pub struct DataModel {
     people: Vec<Person>,
     teams: Vec<TeamData>,
+    repos: Vec<RepoData>,
 }
 
 impl DataModel {
@@ -47,6 +52,11 @@ impl DataModel {
             .expect("Team not found")
     }
 
+    pub fn create_repo(&mut self, repo: RepoDataBuilder) {
+        let repo = repo.build().expect("Cannot build repo");
+        self.repos.push(repo);
+    }
+
     /// Creates a GitHub model from the current team data mock.
     /// Note that all users should have been created before calling this method, so that
     /// GitHub knows about the users' existence.
@@ -82,29 +92,95 @@ impl DataModel {
                 teams.push(api::Team {
                     id: Some(teams.len() as u64),
                     name: gh_team.name.clone(),
-                    description: Some("Managed by the rust-lang/team repository.".to_string()),
+                    description: Some(team.description.clone().unwrap_or_else(|| {
+                        "Managed by the rust-lang/team repository.".to_string()
+                    })),
                     privacy: TeamPrivacy::Closed,
                     slug: gh_team.name.clone(),
+                    parent: None,
                 })
             }
         }
 
+        let org_members = team_memberships
+            .values()
+            .flat_map(|members| members.keys())
+            .copied()
+            .collect();
+
+        let repos = self
+            .repos
+            .iter()
+            .enumerate()
+            .map(|(id, repo)| (repo.name.clone(), repo.to_state(id as u64)))
+            .collect();
+
         GithubMock {
             users,
             owners: Default::default(),
+            org_members,
+            org_invitations: Default::default(),
             teams,
             team_memberships,
             team_invitations: Default::default(),
+            repos,
+            rulesets: Default::default(),
+            app_installations: Default::default(),
         }
     }
 
     pub fn diff_teams(&self, github: GithubMock) -> Vec<TeamDiff> {
+        self.diff_teams_with_config(github, GitHubConfig::default())
+            .expect("Cannot diff teams")
+    }
+
+    pub fn diff_teams_with_config(
+        &self,
+        github: GithubMock,
+        config: GitHubConfig,
+    ) -> anyhow::Result<Vec<TeamDiff>> {
         let teams = self.teams.iter().map(|r| r.to_data()).collect();
         let repos = vec![];
 
         let read = Box::new(github);
-        let sync = SyncGitHub::new(read, teams, repos).expect("Cannot create SyncGitHub");
-        sync.diff_teams().expect("Cannot diff teams")
+        let sync = SyncGitHub::new(read, teams, repos, config).expect("Cannot create SyncGitHub");
+        sync.diff_teams()
+    }
+
+    pub fn diff_repos(
+        &self,
+        github: GithubMock,
+        config: GitHubConfig,
+    ) -> anyhow::Result<Vec<RepoDiff>> {
+        let teams = self.teams.iter().map(|r| r.to_data()).collect();
+        let repos = self.repos.iter().map(|r| r.to_data()).collect();
+
+        let read = Box::new(github);
+        let sync = SyncGitHub::new(read, teams, repos, config).expect("Cannot create SyncGitHub");
+        sync.diff_repos()
+    }
+
+    pub fn diff_all(&self, github: GithubMock, config: GitHubConfig) -> anyhow::Result<Diff> {
+        let teams = self.teams.iter().map(|r| r.to_data()).collect();
+        let repos = self.repos.iter().map(|r| r.to_data()).collect();
+
+        let read = Box::new(github);
+        let sync = SyncGitHub::new(read, teams, repos, config).expect("Cannot create SyncGitHub");
+        sync.diff_all()
+    }
+
+    pub fn diff_org_memberships(
+        &self,
+        github: GithubMock,
+        config: GitHubConfig,
+    ) -> Vec<OrgMembershipDiff> {
+        let teams = self.teams.iter().map(|r| r.to_data()).collect();
+        let repos = vec![];
+
+        let read = Box::new(github);
+        let sync = SyncGitHub::new(read, teams, repos, config).expect("Cannot create SyncGitHub");
+        sync.diff_org_memberships()
+            .expect("Cannot diff org memberships")
     }
 }
 
@@ -115,10 +191,16 @@ pub struct TeamData {
     kind: TeamKind,
     name: String,
     #[builder(default)]
+    subteam_of: Option<String>,
+    #[builder(default)]
     gh_teams: Vec<GitHubTeam>,
+    /// Description of the team on the website.
+    #[builder(default, setter(strip_option))]
+    description: Option<String>,
 }
 
 impl TeamData {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(name: &str) -> TeamDataBuilder {
         TeamDataBuilder::default().name(name.to_string())
     }
@@ -131,6 +213,14 @@ impl TeamData {
         self.github_team(team).members.retain(|u| *u != user);
     }
 
+    pub fn set_parent(&mut self, parent: &str) {
+        self.subteam_of = Some(parent.to_string());
+    }
+
+    pub fn set_description(&mut self, description: &str) {
+        self.description = Some(description.to_string());
+    }
+
     pub fn remove_gh_team(&mut self, name: &str) {
         self.gh_teams.retain(|t| t.name != name);
     }
@@ -146,17 +236,28 @@ impl TeamData {
         let TeamData {
             name,
             kind,
+            subteam_of,
             gh_teams,
+            description,
         } = self.clone();
         rust_team_data::v1::Team {
             name: name.clone(),
             kind,
-            subteam_of: None,
+            subteam_of,
             top_level: None,
             members: vec![],
             alumni: vec![],
             github: (!gh_teams.is_empty()).then_some(TeamGitHub { teams: gh_teams }),
-            website_data: None,
+            website_data: description.map(|description| TeamWebsite {
+                name: name.clone(),
+                description,
+                page: name.clone(),
+                email: None,
+                repo: None,
+                discord: None,
+                zulip_stream: None,
+                weight: 0,
+            }),
             roles: vec![],
             discord: vec![],
         }
@@ -176,21 +277,246 @@ impl TeamDataBuilder {
     }
 }
 
+#[derive(Clone, Builder)]
+#[builder(pattern = "owned")]
+pub struct RepoData {
+    name: String,
+    #[builder(default)]
+    description: String,
+    #[builder(default)]
+    bots: Vec<v1::Bot>,
+    #[builder(default)]
+    teams: Vec<v1::RepoTeam>,
+    #[builder(default)]
+    members: Vec<v1::RepoMember>,
+    #[builder(default)]
+    branch_protections: Vec<v1::BranchProtection>,
+    #[builder(default)]
+    archived: bool,
+    #[builder(default)]
+    private: bool,
+}
+
+impl RepoData {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(name: &str) -> RepoDataBuilder {
+        RepoDataBuilder::default().name(name.to_string())
+    }
+
+    fn to_data(&self) -> v1::Repo {
+        let RepoData {
+            name,
+            description,
+            bots,
+            teams,
+            members,
+            branch_protections,
+            archived,
+            private,
+        } = self.clone();
+        v1::Repo {
+            org: DEFAULT_ORG.to_string(),
+            name,
+            description,
+            homepage: None,
+            bots,
+            teams,
+            members,
+            branch_protections,
+            archived,
+            private,
+            auto_merge_enabled: false,
+        }
+    }
+
+    /// The state of the repo on GitHub matching the data, with the default options.
+    fn to_state(&self, id: u64) -> RepoState {
+        let mut state = repo_state(&self.name, id);
+        state.repo.description = Some(self.description.clone());
+        state.repo.archived = self.archived;
+        if self.private {
+            state.repo.options.visibility = Some(RepoVisibility::Private);
+        }
+        RepoState {
+            teams: self
+                .teams
+                .iter()
+                .map(|team| RepoTeam {
+                    name: team.name.clone(),
+                    permission: convert_permission(&team.permission),
+                })
+                .collect(),
+            collaborators: self
+                .members
+                .iter()
+                .map(|member| RepoUser {
+                    name: member.name.clone(),
+                    permission: convert_permission(&member.permission),
+                })
+                .collect(),
+            ..state
+        }
+    }
+}
+
+/// The state of a repo on GitHub without any setting, team or collaborator.
+fn repo_state(name: &str, id: u64) -> RepoState {
+    RepoState {
+        repo: Repo {
+            node_id: format!("R_{id}"),
+            repo_id: id,
+            name: name.to_string(),
+            org: DEFAULT_ORG.to_string(),
+            description: None,
+            homepage: None,
+            archived: false,
+            allow_auto_merge: Some(false),
+            options: RepoOptions {
+                visibility: Some(RepoVisibility::Public),
+                ..RepoOptions::default()
+            },
+        },
+        teams: vec![],
+        collaborators: vec![],
+        branch_protections: HashMap::new(),
+        topics: vec![],
+    }
+}
+
+impl RepoDataBuilder {
+    pub fn team(mut self, name: &str, permission: v1::RepoPermission) -> Self {
+        let mut teams = self.teams.unwrap_or_default();
+        teams.push(v1::RepoTeam {
+            name: name.to_string(),
+            permission,
+        });
+        self.teams = Some(teams);
+        self
+    }
+}
+
 /// Represents the state of GitHub repositories, teams and users.
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct GithubMock {
     // user ID -> login
     users: HashMap<UserId, String>,
     // org name -> user ID
     owners: HashMap<String, Vec<UserId>>,
+    // Members of the org, including the owners
+    org_members: HashSet<UserId>,
+    // List of users invited to the org
+    org_invitations: Vec<String>,
     teams: Vec<Team>,
     // Team name -> members
     team_memberships: HashMap<String, HashMap<UserId, TeamMember>>,
     // Team name -> list of invited users
     team_invitations: HashMap<String, Vec<String>>,
+    // Repo name -> state of the repo
+    repos: HashMap<String, RepoState>,
+    // Repo name -> rulesets by name, along with their IDs
+    rulesets: HashMap<String, HashMap<String, (u64, Ruleset)>>,
+    // Installation ID -> ID of the app and names of the repos it's installed on
+    app_installations: HashMap<u64, (u64, Vec<String>)>,
 }
 
 impl GithubMock {
+    pub fn add_owner(&mut self, user: UserId) {
+        self.owners
+            .entry(DEFAULT_ORG.to_string())
+            .or_default()
+            .push(user);
+        self.org_members.insert(user);
+    }
+
+    pub fn add_org_member(&mut self, user: UserId) {
+        self.org_members.insert(user);
+    }
+
+    pub fn add_org_invitation(&mut self, user: &str) {
+        self.org_invitations.push(user.to_string());
+    }
+
+    pub fn set_team_parent(&mut self, team: &str, parent: &str) {
+        let team = self
+            .teams
+            .iter_mut()
+            .find(|t| t.name == team)
+            .expect("GitHub team not found");
+        team.parent = Some(api::ParentTeam {
+            name: parent.to_string(),
+        });
+    }
+
+    /// Adds a repo that is only on GitHub, and returns its state so that it can be changed.
+    pub fn add_repo(&mut self, name: &str) -> &mut RepoState {
+        let state = repo_state(name, self.repos.len() as u64);
+        self.repos.entry(name.to_string()).or_insert(state)
+    }
+
+    pub fn set_repo_options(&mut self, repo: &str, options: RepoOptions) {
+        self.repo_state(repo).repo.options = options;
+    }
+
+    pub fn set_repo_topics(&mut self, repo: &str, topics: &[&str]) {
+        self.repo_state(repo).topics = topics.iter().map(|t| t.to_string()).collect();
+    }
+
+    /// Gives a team a permission on a repo, replacing its current one.
+    pub fn set_repo_team(&mut self, repo: &str, team: &str, permission: RepoPermission) {
+        let teams = &mut self.repo_state(repo).teams;
+        teams.retain(|t| t.name != team);
+        teams.push(RepoTeam {
+            name: team.to_string(),
+            permission,
+        });
+    }
+
+    /// Adds a branch protection that only requires pull requests, and returns it so that its
+    /// settings can be changed.
+    pub fn add_branch_protection(
+        &mut self,
+        repo: &str,
+        id: &str,
+        pattern: &str,
+    ) -> &mut BranchProtection {
+        let protection = BranchProtection {
+            pattern: pattern.to_string(),
+            is_admin_enforced: true,
+            dismisses_stale_reviews: false,
+            required_approving_review_count: 0,
+            required_status_checks: vec![],
+            push_allowances: vec![],
+            requires_approving_reviews: true,
+            requires_strict_status_checks: false,
+            requires_code_owner_reviews: false,
+            requires_linear_history: false,
+            requires_conversation_resolution: false,
+            allows_force_pushes: false,
+            allows_deletions: false,
+            review_dismissal_allowances: vec![],
+        };
+        let protections = &mut self.repo_state(repo).branch_protections;
+        protections.insert(pattern.to_string(), (id.to_string(), protection));
+        &mut protections.get_mut(pattern).unwrap().1
+    }
+
+    pub fn add_ruleset(&mut self, repo: &str, id: u64, ruleset: Ruleset) {
+        self.rulesets
+            .entry(repo.to_string())
+            .or_default()
+            .insert(ruleset.name.clone(), (id, ruleset.normalized()));
+    }
+
+    pub fn add_app_installation(&mut self, installation_id: u64, app_id: u64, repos: &[&str]) {
+        let repos = repos.iter().map(|r| r.to_string()).collect();
+        self.app_installations
+            .insert(installation_id, (app_id, repos));
+    }
+
+    fn repo_state(&mut self, repo: &str) -> &mut RepoState {
+        self.repos.get_mut(repo).expect("GitHub repo not found")
+    }
+
     pub fn add_invitation(&mut self, repo: &str, user: &str) {
         self.team_invitations
             .entry(repo.to_string())
@@ -219,15 +545,41 @@ impl GithubRead for GithubMock {
             .collect())
     }
 
-    fn org_app_installations(&self, _org: &str) -> anyhow::Result<Vec<OrgAppInstallation>> {
-        Ok(vec![])
+    fn org_members(&self, org: &str) -> anyhow::Result<HashMap<UserId, String>> {
+        assert_eq!(org, DEFAULT_ORG);
+        Ok(self
+            .org_members
+            .iter()
+            .map(|user| (*user, self.users[user].clone()))
+            .collect())
+    }
+
+    fn org_invitations(&self, org: &str) -> anyhow::Result<HashSet<String>> {
+        assert_eq!(org, DEFAULT_ORG);
+        Ok(self.org_invitations.iter().cloned().collect())
+    }
+
+    fn org_app_installations(&self, org: &str) -> anyhow::Result<Vec<OrgAppInstallation>> {
+        assert_eq!(org, DEFAULT_ORG);
+        Ok(self
+            .app_installations
+            .iter()
+            .map(|(installation_id, (app_id, _))| OrgAppInstallation {
+                installation_id: *installation_id,
+                app_id: *app_id,
+            })
+            .collect())
     }
 
     fn app_installation_repos(
         &self,
-        _installation_id: u64,
+        installation_id: u64,
     ) -> anyhow::Result<Vec<RepoAppInstallation>> {
-        Ok(vec![])
+        Ok(self.app_installations[&installation_id]
+            .1
+            .iter()
+            .map(|name| RepoAppInstallation { name: name.clone() })
+            .collect())
     }
 
     fn org_teams(&self, org: &str) -> anyhow::Result<Vec<(String, String)>> {
@@ -267,23 +619,50 @@ impl GithubRead for GithubMock {
             .collect())
     }
 
-    fn repo(&self, _org: &str, _repo: &str) -> anyhow::Result<Option<Repo>> {
-        todo!()
+    fn org_repositories(&self, org: &str) -> anyhow::Result<Vec<Repo>> {
+        assert_eq!(org, DEFAULT_ORG);
+        Ok(self
+            .repos
+            .values()
+            .map(|state| state.repo.clone())
+            .collect())
     }
 
-    fn repo_teams(&self, _org: &str, _repo: &str) -> anyhow::Result<Vec<RepoTeam>> {
-        todo!()
+    fn repo(&self, org: &str, repo: &str) -> anyhow::Result<Option<Repo>> {
+        assert_eq!(org, DEFAULT_ORG);
+        Ok(self.repos.get(repo).map(|state| state.repo.clone()))
     }
 
-    fn repo_collaborators(&self, _org: &str, _repo: &str) -> anyhow::Result<Vec<RepoUser>> {
-        todo!()
+    fn repo_teams(&self, org: &str, repo: &str) -> anyhow::Result<Vec<RepoTeam>> {
+        assert_eq!(org, DEFAULT_ORG);
+        Ok(self.repos[repo].teams.clone())
+    }
+
+    fn repo_collaborators(&self, org: &str, repo: &str) -> anyhow::Result<Vec<RepoUser>> {
+        assert_eq!(org, DEFAULT_ORG);
+        Ok(self.repos[repo].collaborators.clone())
     }
 
     fn branch_protections(
         &self,
-        _org: &str,
-        _repo: &str,
+        org: &str,
+        repo: &str,
     ) -> anyhow::Result<HashMap<String, (String, BranchProtection)>> {
-        todo!()
+        assert_eq!(org, DEFAULT_ORG);
+        Ok(self.repos[repo].branch_protections.clone())
+    }
+
+    fn repo_topics(&self, org: &str, repo: &str) -> anyhow::Result<Vec<String>> {
+        assert_eq!(org, DEFAULT_ORG);
+        Ok(self.repos[repo].topics.clone())
+    }
+
+    fn repo_rulesets(
+        &self,
+        org: &str,
+        repo: &str,
+    ) -> anyhow::Result<HashMap<String, (u64, Ruleset)>> {
+        assert_eq!(org, DEFAULT_ORG);
+        Ok(self.rulesets.get(repo).cloned().unwrap_or_default())
     }
 }
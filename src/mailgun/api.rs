@@ -1,7 +1,8 @@
+use crate::retry::RetryPolicy;
 use anyhow::Error;
-use log::info;
+use log::debug;
 use reqwest::{
-    blocking::{Client, RequestBuilder},
+    blocking::{Client, RequestBuilder, Response},
     header::{self, HeaderValue},
     Method,
 };
@@ -9,14 +10,16 @@ use reqwest::{
 pub(super) struct Mailgun {
     token: String,
     client: Client,
+    retry: RetryPolicy,
     dry_run: bool,
 }
 
 impl Mailgun {
-    pub(super) fn new(token: &str, dry_run: bool) -> Self {
+    pub(super) fn new(token: &str, retry: RetryPolicy, dry_run: bool) -> Self {
         Self {
             token: token.into(),
             client: Client::new(),
+            retry,
             dry_run,
         }
     }
@@ -28,8 +31,7 @@ impl Mailgun {
             "routes".into()
         };
         Ok(self
-            .request(Method::GET, &url)
-            .send()?
+            .send(Method::GET, &url, &[])?
             .error_for_status()?
             .json()?)
     }
@@ -55,9 +57,7 @@ impl Mailgun {
             form.push(("action", action.as_str()));
         }
 
-        self.request(Method::POST, "routes")
-            .form(&form)
-            .send()?
+        self.send(Method::POST, "routes", &form)?
             .error_for_status()?;
 
         Ok(())
@@ -79,26 +79,34 @@ impl Mailgun {
             form.push(("action", action.as_str()));
         }
 
-        self.request(Method::PUT, &format!("routes/{id}"))
-            .form(&form)
-            .send()?
+        self.send(Method::PUT, &format!("routes/{id}"), &form)?
             .error_for_status()?;
 
         Ok(())
     }
 
     pub(super) fn delete_route(&self, id: &str) -> Result<(), Error> {
-        info!("deleting route with ID {}", id);
+        debug!("deleting route with ID {}", id);
         if self.dry_run {
             return Ok(());
         }
 
-        self.request(Method::DELETE, &format!("routes/{id}"))
-            .send()?
+        self.send(Method::DELETE, &format!("routes/{id}"), &[])?
             .error_for_status()?;
         Ok(())
     }
 
+    /// Send a request with a form body, retrying it on transient failures when it's idempotent:
+    /// creating a route twice would result in a duplicate route.
+    fn send(&self, method: Method, url: &str, form: &[(&str, &str)]) -> Result<Response, Error> {
+        let idempotent = method.is_idempotent();
+        let mut request = self.request(method, url);
+        if !form.is_empty() {
+            request = request.form(form);
+        }
+        self.retry.send(&request, idempotent)
+    }
+
     fn request(&self, method: Method, url: &str) -> RequestBuilder {
         let url = if url.starts_with("https://") {
             url.into()
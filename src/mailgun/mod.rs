@@ -4,9 +4,12 @@ use std::collections::{HashMap, HashSet};
 use std::str;
 
 use self::api::Mailgun;
+use crate::config::Config;
+use crate::failures::Failures;
+use crate::safety::Removals;
+use crate::service::SyncService;
 use crate::TeamApi;
-use anyhow::{bail, Context};
-use log::info;
+use anyhow::bail;
 use rust_team_data::{email_encryption, v1 as team_data};
 
 const DESCRIPTION: &str = "managed by an automatic script on github";
@@ -16,20 +19,33 @@ const ACTIONS_SIZE_LIMIT_BYTES: usize = 4000;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 struct List {
+    /// The address of the list as written in the team repo, which can be encrypted.
+    raw_address: String,
     address: String,
-    members: Vec<String>,
+    members: Vec<Member>,
     priority: i32,
 }
 
+/// A member of a mailing list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Member {
+    /// The address as written in the team repo, which is encrypted for the members who don't want
+    /// it to be public. This is the only form of the address shown in the plan.
+    raw: String,
+    /// The decrypted address, only used to compare the list with the Mailgun routes.
+    address: String,
+}
+
 fn mangle_lists(email_encryption_key: &str, lists: team_data::Lists) -> anyhow::Result<Vec<List>> {
     let mut result = Vec::new();
 
-    for (_key, mut list) in lists.lists.into_iter() {
+    for (_key, list) in lists.lists.into_iter() {
         // Handle encrypted list addresses.
-        list.address = email_encryption::try_decrypt(email_encryption_key, &list.address)?;
+        let address = email_encryption::try_decrypt(email_encryption_key, &list.address)?;
 
         let base_list = List {
-            address: mangle_address(&list.address)?,
+            address: mangle_address(&address)?,
+            raw_address: list.address,
             members: Vec::new(),
             priority: 0,
         };
@@ -48,11 +64,14 @@ fn mangle_lists(email_encryption_key: &str, lists: team_data::Lists) -> anyhow::
         let mut current_list = base_list.clone();
         let mut current_actions_len = 0;
         let mut partitions_count = 0;
-        for mut member in list.members {
+        for raw in list.members {
             // Handle encrypted member email addresses.
-            member = email_encryption::try_decrypt(email_encryption_key, &member)?;
+            let member = Member {
+                address: email_encryption::try_decrypt(email_encryption_key, &raw)?,
+                raw,
+            };
 
-            let action = build_route_action(&member);
+            let action = build_route_action(&member.address);
             if current_actions_len + action.len() > ACTIONS_SIZE_LIMIT_BYTES {
                 partitions_count += 1;
                 result.push(current_list);
@@ -86,32 +105,77 @@ fn mangle_address(addr: &str) -> anyhow::Result<String> {
     }
 }
 
-pub(crate) fn run(
-    token: &str,
-    email_encryption_key: &str,
-    team_api: &TeamApi,
-    dry_run: bool,
-) -> anyhow::Result<()> {
-    let mailgun = Mailgun::new(token, dry_run);
-    let mailmap = team_api.get_lists()?;
-
-    // Mangle all the mailing lists
-    let lists = mangle_lists(email_encryption_key, mailmap)?;
-
-    let mut routes = Vec::new();
-    let mut response = mailgun.get_routes(None)?;
-    let mut cur = 0u64;
-    while !response.items.is_empty() {
-        cur += response.items.len() as u64;
-        routes.extend(response.items);
-        if cur >= response.total_count {
-            break;
+/// The address matched by a route expression built with `mangle_address`.
+fn unmangle_address(expr: &str) -> String {
+    expr.trim_start_matches('^')
+        .trim_end_matches('$')
+        .replace("(?:\\+.+)?", "")
+        .replace("\\.", ".")
+}
+
+/// Synchronization of the mailing lists defined in the team repo with Mailgun routes.
+pub(crate) struct SyncMailgun {
+    mailgun: Mailgun,
+    encryption_key: String,
+    lists: Vec<List>,
+}
+
+impl SyncService for SyncMailgun {
+    type Diff = Diff;
+
+    fn load(team_api: &TeamApi, config: &Config, dry_run: bool) -> anyhow::Result<Self> {
+        let token = crate::get_env("MAILGUN_API_TOKEN")?;
+        let encryption_key = crate::get_env("EMAIL_ENCRYPTION_KEY")?;
+        let mailmap = team_api.get_lists()?;
+
+        Ok(Self {
+            mailgun: Mailgun::new(&token, config.retry, dry_run),
+            // Mangle all the mailing lists
+            lists: mangle_lists(&encryption_key, mailmap)?,
+            encryption_key,
+        })
+    }
+
+    fn diff(&self) -> anyhow::Result<Diff> {
+        let mut routes = Vec::new();
+        let mut response = self.mailgun.get_routes(None)?;
+        let mut cur = 0u64;
+        while !response.items.is_empty() {
+            cur += response.items.len() as u64;
+            routes.extend(response.items);
+            if cur >= response.total_count {
+                break;
+            }
+            response = self.mailgun.get_routes(Some(cur))?;
+        }
+
+        diff_routes(&self.lists, routes)
+    }
+
+    fn removals(&self, diff: &Diff) -> Removals {
+        let mut removals = Removals::default();
+        for route_diff in &diff.route_diffs {
+            match route_diff {
+                RouteDiff::Delete(d) => removals.route_deletions.push(d.address.clone()),
+                RouteDiff::Update(u) => removals.group(
+                    format!("list {}", u.address),
+                    u.members.len() + u.member_removals.len() - u.member_additions.len(),
+                    u.member_removals.len(),
+                ),
+                RouteDiff::Create(_) => {}
+            }
         }
-        response = mailgun.get_routes(Some(cur))?;
+        removals
+    }
+
+    fn apply(&self, diff: Diff, failures: &mut Failures) -> anyhow::Result<()> {
+        diff.apply(&self.mailgun, &self.encryption_key, failures)
     }
+}
 
+fn diff_routes(lists: &[List], routes: Vec<api::Route>) -> anyhow::Result<Diff> {
     let mut addr2list = HashMap::new();
-    for list in &lists {
+    for list in lists {
         if addr2list
             .insert((list.address.clone(), list.priority), list)
             .is_some()
@@ -124,6 +188,7 @@ pub(crate) fn run(
         }
     }
 
+    let mut route_diffs = Vec::new();
     for route in routes {
         if route.description != DESCRIPTION {
             continue;
@@ -131,53 +196,260 @@ pub(crate) fn run(
         let address = extract(&route.expression, "match_recipient(\"", "\")");
         let key = (address.to_string(), route.priority);
         match addr2list.remove(&key) {
-            Some(new_list) => sync(&mailgun, &route, new_list)
-                .with_context(|| format!("failed to sync {address}"))?,
-            None => mailgun
-                .delete_route(&route.id)
-                .with_context(|| format!("failed to delete {address}"))?,
+            Some(new_list) => {
+                let before = route
+                    .actions
+                    .iter()
+                    .map(|action| extract(action, "forward(\"", "\")"))
+                    .collect::<Vec<_>>();
+                let after = new_list
+                    .members
+                    .iter()
+                    .map(|m| m.address.as_str())
+                    .collect::<HashSet<_>>();
+                let member_additions = new_list
+                    .members
+                    .iter()
+                    .filter(|m| !before.contains(&m.address.as_str()))
+                    .map(|m| m.raw.clone())
+                    .collect::<Vec<_>>();
+                // The removed members are only known from Mailgun, which doesn't tell whether
+                // their address is encrypted in the team repo.
+                let member_removals = before
+                    .iter()
+                    .filter(|m| !after.contains(*m))
+                    .map(|m| mask_address(m))
+                    .collect::<Vec<_>>();
+                if !member_additions.is_empty() || !member_removals.is_empty() {
+                    route_diffs.push(RouteDiff::Update(UpdateRouteDiff {
+                        id: route.id,
+                        address: new_list.raw_address.clone(),
+                        priority: new_list.priority,
+                        members: raw_members(new_list),
+                        member_additions,
+                        member_removals,
+                    }));
+                }
+            }
+            // The list isn't in the team repo anymore, so whether its address was encrypted is
+            // unknown.
+            None => route_diffs.push(RouteDiff::Delete(DeleteRouteDiff {
+                id: route.id,
+                address: mask_address(&unmangle_address(address)),
+                priority: route.priority,
+            })),
         }
     }
 
-    for (_, list) in addr2list.iter() {
-        create(&mailgun, list).with_context(|| format!("failed to create {}", list.address))?;
+    // Iterate over the lists rather than the map to keep the diff stable across runs.
+    for list in lists {
+        if addr2list
+            .remove(&(list.address.clone(), list.priority))
+            .is_some()
+        {
+            route_diffs.push(RouteDiff::Create(CreateRouteDiff {
+                address: list.raw_address.clone(),
+                priority: list.priority,
+                members: raw_members(list),
+            }));
+        }
     }
 
-    Ok(())
+    Ok(Diff { route_diffs })
+}
+
+fn raw_members(list: &List) -> Vec<String> {
+    list.members.iter().map(|m| m.raw.clone()).collect()
+}
+
+/// Hide the user part of an address, keeping only its first character.
+fn mask_address(address: &str) -> String {
+    match address.split_once('@') {
+        Some((user, domain)) => {
+            let first = user.chars().next().map(String::from).unwrap_or_default();
+            format!("{first}***@{domain}")
+        }
+        None => "***".to_string(),
+    }
+}
+
+fn decrypt_all(encryption_key: &str, values: &[String]) -> anyhow::Result<Vec<String>> {
+    values
+        .iter()
+        .map(|value| Ok(email_encryption::try_decrypt(encryption_key, value)?))
+        .collect()
 }
 
 fn build_route_action(member: &str) -> String {
     format!("forward(\"{member}\")")
 }
 
-fn build_route_actions(list: &List) -> impl Iterator<Item = String> + '_ {
-    list.members.iter().map(|member| build_route_action(member))
+fn build_route_actions(members: &[String]) -> impl Iterator<Item = String> + '_ {
+    members.iter().map(|member| build_route_action(member))
 }
 
-fn create(mailgun: &Mailgun, list: &List) -> anyhow::Result<()> {
-    info!("creating list {}", list.address);
+/// The changes needed to synchronize the Mailgun routes with the mailing lists.
+///
+/// The addresses are kept as they're written in the team repo, and only decrypted when the
+/// changes are applied, so that the printed and saved plans don't reveal the encrypted ones.
+#[derive(serde::Serialize)]
+pub(crate) struct Diff {
+    route_diffs: Vec<RouteDiff>,
+}
 
-    let expr = format!("match_recipient(\"{}\")", list.address);
-    let actions = build_route_actions(list).collect::<Vec<_>>();
-    mailgun.create_route(list.priority, DESCRIPTION, &expr, &actions)?;
-    Ok(())
+impl Diff {
+    fn apply(
+        &self,
+        mailgun: &Mailgun,
+        encryption_key: &str,
+        failures: &mut Failures,
+    ) -> anyhow::Result<()> {
+        for route_diff in &self.route_diffs {
+            failures.handle(route_diff.apply(mailgun, encryption_key), || {
+                route_diff.context()
+            })?;
+        }
+        Ok(())
+    }
 }
 
-fn sync(mailgun: &Mailgun, route: &api::Route, list: &List) -> anyhow::Result<()> {
-    let before = route
-        .actions
-        .iter()
-        .map(|action| extract(action, "forward(\"", "\")"))
-        .collect::<HashSet<_>>();
-    let after = list.members.iter().map(|s| &s[..]).collect::<HashSet<_>>();
-    if before == after {
-        return Ok(());
+impl std::fmt::Display for Diff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "💻 Route Diffs:")?;
+        for route_diff in &self.route_diffs {
+            write!(f, "{route_diff}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum RouteDiff {
+    Create(CreateRouteDiff),
+    Update(UpdateRouteDiff),
+    Delete(DeleteRouteDiff),
+}
+
+impl RouteDiff {
+    fn apply(&self, mailgun: &Mailgun, encryption_key: &str) -> anyhow::Result<()> {
+        match self {
+            RouteDiff::Create(c) => c.apply(mailgun, encryption_key),
+            RouteDiff::Update(u) => u.apply(mailgun, encryption_key),
+            RouteDiff::Delete(d) => d.apply(mailgun),
+        }
+    }
+
+    fn context(&self) -> String {
+        match self {
+            RouteDiff::Create(c) => format!("creating list {}", c.address),
+            RouteDiff::Update(u) => format!("updating list {}", u.address),
+            RouteDiff::Delete(d) => format!("deleting list {}", d.address),
+        }
+    }
+}
+
+impl std::fmt::Display for RouteDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RouteDiff::Create(c) => write!(f, "{c}"),
+            RouteDiff::Update(u) => write!(f, "{u}"),
+            RouteDiff::Delete(d) => write!(f, "{d}"),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct CreateRouteDiff {
+    address: String,
+    priority: i32,
+    members: Vec<String>,
+}
+
+impl CreateRouteDiff {
+    fn apply(&self, mailgun: &Mailgun, encryption_key: &str) -> anyhow::Result<()> {
+        let address = email_encryption::try_decrypt(encryption_key, &self.address)?;
+        let expr = format!("match_recipient(\"{}\")", mangle_address(&address)?);
+        let members = decrypt_all(encryption_key, &self.members)?;
+        let actions = build_route_actions(&members).collect::<Vec<_>>();
+        mailgun.create_route(self.priority, DESCRIPTION, &expr, &actions)?;
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for CreateRouteDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "➕ Creating list:")?;
+        writeln!(f, "  Address: {}", self.address)?;
+        writeln!(f, "  Priority: {}", self.priority)?;
+        writeln!(f, "  Members:")?;
+        for member in &self.members {
+            writeln!(f, "    {member}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(serde::Serialize)]
+struct UpdateRouteDiff {
+    id: String,
+    address: String,
+    priority: i32,
+    /// The full list of members after the update, as Mailgun replaces all the route actions.
+    members: Vec<String>,
+    member_additions: Vec<String>,
+    /// The masked addresses of the removed members.
+    member_removals: Vec<String>,
+}
+
+impl UpdateRouteDiff {
+    fn apply(&self, mailgun: &Mailgun, encryption_key: &str) -> anyhow::Result<()> {
+        let members = decrypt_all(encryption_key, &self.members)?;
+        let actions = build_route_actions(&members).collect::<Vec<_>>();
+        mailgun.update_route(&self.id, self.priority, &actions)?;
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for UpdateRouteDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "📝 Updating list:")?;
+        writeln!(f, "  Address: {}", self.address)?;
+        writeln!(f, "  Priority: {}", self.priority)?;
+        writeln!(f, "  Members:")?;
+        for member in &self.member_additions {
+            writeln!(f, "    ➕ {member}")?;
+        }
+        for member in &self.member_removals {
+            writeln!(f, "    − {member}")?;
+        }
+        Ok(())
     }
+}
 
-    info!("updating list {}", list.address);
-    let actions = build_route_actions(list).collect::<Vec<_>>();
-    mailgun.update_route(&route.id, list.priority, &actions)?;
-    Ok(())
+#[derive(serde::Serialize)]
+struct DeleteRouteDiff {
+    id: String,
+    /// The masked address of the list.
+    address: String,
+    priority: i32,
+}
+
+impl DeleteRouteDiff {
+    fn apply(&self, mailgun: &Mailgun) -> anyhow::Result<()> {
+        mailgun.delete_route(&self.id)?;
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for DeleteRouteDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "❌ Deleting list '{}' (priority {})",
+            self.address, self.priority
+        )
+    }
 }
 
 fn extract<'a>(s: &'a str, prefix: &str, suffix: &str) -> &'a str {
@@ -190,17 +462,20 @@ fn extract<'a>(s: &'a str, prefix: &str, suffix: &str) -> &'a str {
 mod tests {
     use super::*;
 
+    fn member(address: &str) -> Member {
+        Member {
+            raw: address.into(),
+            address: address.into(),
+        }
+    }
+
     #[test]
     fn test_build_route_actions() {
-        let list = List {
-            address: "list@example.com".into(),
-            members: vec![
-                "foo@example.com".into(),
-                "bar@example.com".into(),
-                "baz@example.net".into(),
-            ],
-            priority: 0,
-        };
+        let members = vec![
+            "foo@example.com".to_string(),
+            "bar@example.com".to_string(),
+            "baz@example.net".to_string(),
+        ];
 
         assert_eq!(
             vec![
@@ -208,10 +483,87 @@ mod tests {
                 "forward(\"bar@example.com\")",
                 "forward(\"baz@example.net\")",
             ],
-            build_route_actions(&list).collect::<Vec<_>>()
+            build_route_actions(&members).collect::<Vec<_>>()
         );
     }
 
+    #[test]
+    fn test_diff_routes() {
+        let list = |address: &str, priority, members: &[&str]| List {
+            raw_address: address.into(),
+            address: address.into(),
+            members: members.iter().map(|m| member(m)).collect(),
+            priority,
+        };
+        let route = |id: &str, address: &str, priority, members: &[&str]| api::Route {
+            actions: members.iter().map(|m| build_route_action(m)).collect(),
+            expression: format!("match_recipient(\"{address}\")"),
+            id: id.into(),
+            priority,
+            description: DESCRIPTION.into(),
+        };
+
+        let lists = vec![
+            list("unchanged", 0, &["foo@example.com"]),
+            list("updated", 0, &["foo@example.com", "bar@example.com"]),
+            list("updated", 1, &["baz@example.com"]),
+            list("created", 0, &["foo@example.com"]),
+            List {
+                raw_address: "encrypted:secret".into(),
+                address: "secret".into(),
+                members: vec![Member {
+                    raw: "encrypted:member".into(),
+                    address: "member@example.com".into(),
+                }],
+                priority: 0,
+            },
+        ];
+        let mut unmanaged = route("5", "unmanaged", 0, &["foo@example.com"]);
+        unmanaged.description = "created by hand".into();
+        let routes = vec![
+            route("1", "unchanged", 0, &["foo@example.com"]),
+            route("2", "updated", 0, &["foo@example.com", "qux@example.com"]),
+            route("3", "updated", 1, &["qux@example.com"]),
+            route(
+                "4",
+                &mangle_address("deleted@example.com").unwrap(),
+                0,
+                &["foo@example.com"],
+            ),
+            route("6", "secret", 0, &["removed@example.com"]),
+            unmanaged,
+        ];
+
+        let diff = diff_routes(&lists, routes).unwrap();
+        insta::assert_snapshot!(diff.to_string(), @r###"
+        💻 Route Diffs:
+        📝 Updating list:
+          Address: updated
+          Priority: 0
+          Members:
+            ➕ bar@example.com
+            − q***@example.com
+        📝 Updating list:
+          Address: updated
+          Priority: 1
+          Members:
+            ➕ baz@example.com
+            − q***@example.com
+        ❌ Deleting list 'd***@example.com' (priority 0)
+        📝 Updating list:
+          Address: encrypted:secret
+          Priority: 0
+          Members:
+            ➕ encrypted:member
+            − r***@example.com
+        ➕ Creating list:
+          Address: created
+          Priority: 0
+          Members:
+            foo@example.com
+        "###);
+    }
+
     #[test]
     fn test_mangle_address() {
         assert_eq!(
@@ -219,6 +571,10 @@ mod tests {
             mangle_address("list-name@example.com").unwrap()
         );
         assert!(mangle_address("list-name.example.com").is_err());
+        assert_eq!(
+            "list-name@example.com",
+            unmangle_address(&mangle_address("list-name@example.com").unwrap())
+        );
     }
 
     #[test]
@@ -241,8 +597,8 @@ mod tests {
                     ],
                 },
                 secret_list.clone() => rust_team_data::v1::List {
-                    address: secret_list,
-                    members: vec![secret_member, "baz@example.com".into()]
+                    address: secret_list.clone(),
+                    members: vec![secret_member.clone(), "baz@example.com".into()]
                 },
                 "big@example.com".into() => rust_team_data::v1::List {
                     address: "big@example.com".into(),
@@ -254,42 +610,51 @@ mod tests {
         };
 
         let mangled = mangle_lists(ENCRYPTION_KEY, original).unwrap();
+        let secret = Member {
+            raw: secret_member,
+            address: "secret-member@example.com".into(),
+        };
         let expected = vec![
             List {
+                raw_address: "small@example.com".into(),
                 address: mangle_address("small@example.com").unwrap(),
                 priority: 0,
                 members: vec![
-                    "foo@example.com".into(),
-                    "bar@example.com".into(),
-                    "secret-member@example.com".into(),
+                    member("foo@example.com"),
+                    member("bar@example.com"),
+                    secret.clone(),
                 ],
             },
             List {
+                raw_address: secret_list,
                 address: mangle_address("secret-list@example.com").unwrap(),
                 priority: 0,
-                members: vec!["secret-member@example.com".into(), "baz@example.com".into()],
+                members: vec![secret, member("baz@example.com")],
             },
             // With ACTIONS_SIZE_LIMIT_BYTES = 4000, each list can contain at most 137 users named
             // `fooNNN@example.com`. If the limit is changed the numbers will need to be updated.
             List {
+                raw_address: "big@example.com".into(),
                 address: mangle_address("big@example.com").unwrap(),
                 priority: 0,
                 members: (0..137)
-                    .map(|i| format!("foo{i:03}@example.com"))
+                    .map(|i| member(&format!("foo{i:03}@example.com")))
                     .collect::<Vec<_>>(),
             },
             List {
+                raw_address: "big@example.com".into(),
                 address: mangle_address("big@example.com").unwrap(),
                 priority: 1,
                 members: (137..274)
-                    .map(|i| format!("foo{i:03}@example.com"))
+                    .map(|i| member(&format!("foo{i:03}@example.com")))
                     .collect::<Vec<_>>(),
             },
             List {
+                raw_address: "big@example.com".into(),
                 address: mangle_address("big@example.com").unwrap(),
                 priority: 2,
                 members: (274..300)
-                    .map(|i| format!("foo{i:03}@example.com"))
+                    .map(|i| member(&format!("foo{i:03}@example.com")))
                     .collect::<Vec<_>>(),
             },
         ];
@@ -1,14 +1,29 @@
+mod config;
+mod failures;
 mod github;
 mod mailgun;
+#[cfg(test)]
+mod mock_server;
+mod plan;
+mod retry;
+mod safety;
+mod service;
 mod team_api;
 mod utils;
 mod zulip;
 
-use crate::github::{create_diff, GitHubApiRead, GitHubWrite, HttpClient};
+use crate::config::Config;
+use crate::failures::Failures;
+use crate::github::GitHubSync;
+use crate::mailgun::SyncMailgun;
+use crate::plan::Plan;
+use crate::safety::Removals;
+use crate::service::SyncService;
 use crate::team_api::TeamApi;
 use crate::zulip::SyncZulip;
-use anyhow::Context;
+use anyhow::{bail, Context};
 use log::{error, info, warn};
+use std::path::PathBuf;
 
 const AVAILABLE_SERVICES: &[&str] = &["github", "mailgun", "zulip"];
 const USER_AGENT: &str = "rust-lang teams sync (https://github.com/rust-lang/sync-team)";
@@ -22,7 +37,13 @@ fn usage() {
     eprintln!("  --help              Show this help message");
     eprintln!("  --live              Apply the proposed changes to the services");
     eprintln!("  --team-repo <path>  Path to the local team repo to use");
+    eprintln!("  --config <path>     Path to the JSON configuration of the tool");
     eprintln!("  --only-print-plan   Print the execution plan without executing it");
+    eprintln!("  --plan-format <fmt> Format of the printed plan: `text` (default) or `json`");
+    eprintln!("  --save-plan <path>  Save the execution plan to a file without executing it");
+    eprintln!("  --apply-plan <path> Execute a plan saved with --save-plan, if it's still current");
+    eprintln!("  --keep-going        Keep applying independent changes after a failure");
+    eprintln!("  --allow-destructive Apply plans exceeding the configured safety limits");
     eprintln!("environment variables:");
     eprintln!("  GITHUB_TOKEN          Authentication token with GitHub");
     eprintln!("  MAILGUN_API_TOKEN     Authentication token with Mailgun");
@@ -31,26 +52,118 @@ fn usage() {
     eprintln!("  ZULIP_API_TOKEN       Autnentication token of the Zulip bot");
 }
 
+/// How the execution plan of each service is printed.
+#[derive(Copy, Clone, PartialEq)]
+enum PlanFormat {
+    /// Human readable plan, printed in the logs.
+    Text,
+    /// Machine readable plan, printed on stdout as a single JSON document at the end of the run.
+    Json,
+}
+
+impl std::str::FromStr for PlanFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(PlanFormat::Text),
+            "json" => Ok(PlanFormat::Json),
+            _ => anyhow::bail!("unknown plan format: {s} (expected `text` or `json`)"),
+        }
+    }
+}
+
+/// How the services should be synchronized.
+struct SyncOptions {
+    config: Config,
+    dry_run: bool,
+    allow_destructive: bool,
+    format: PlanFormat,
+    only_print_plan: bool,
+    save_plan: Option<PathBuf>,
+    saved_plan: Option<Plan>,
+}
+
+impl SyncOptions {
+    /// Compute the plan of a service, print it, record it in `plan` and make sure it matches the
+    /// plan being applied, if any. The plan is then applied unless only a plan was requested.
+    fn synchronize<S: SyncService>(
+        &self,
+        name: &str,
+        team_api: &TeamApi,
+        plan: &mut Plan,
+        failures: &mut Failures,
+    ) -> anyhow::Result<()> {
+        let service = S::load(team_api, &self.config, self.dry_run)?;
+        let diff = service.diff()?;
+        if self.format == PlanFormat::Text {
+            info!("{}", diff);
+        }
+        plan.insert(name, &diff)?;
+        if let Some(saved_plan) = &self.saved_plan {
+            saved_plan.ensure_unchanged(name, &diff)?;
+            info!("the plan of {name} is unchanged since it was saved");
+        }
+        let checked = service.check(&diff);
+        if !self.only_print_plan && self.save_plan.is_none() {
+            self.check_safety_limits(name, &service.removals(&diff))?;
+            service.apply(diff, failures)?;
+        }
+        checked
+    }
+
+    /// Refuse to apply plans exceeding the safety limits, unless `--allow-destructive` is passed.
+    fn check_safety_limits(&self, name: &str, removals: &Removals) -> anyhow::Result<()> {
+        let violations = self.config.safety.violations(removals);
+        if violations.is_empty() {
+            return Ok(());
+        }
+        if self.allow_destructive {
+            for violation in &violations {
+                warn!("applying the plan of {name} despite the safety limits: {violation}");
+            }
+            return Ok(());
+        }
+        let mut report = format!("the plan of {name} exceeds the safety limits:");
+        for violation in &violations {
+            report.push_str("\n  - ");
+            report.push_str(violation);
+        }
+        bail!("{report}\npass --allow-destructive to apply it anyway");
+    }
+}
+
 fn app() -> anyhow::Result<()> {
     let mut dry_run = true;
-    let mut next_team_repo = false;
     let mut only_print_plan = false;
+    let mut keep_going = false;
+    let mut allow_destructive = false;
+    let mut plan_format = PlanFormat::Text;
+    let mut save_plan = None;
+    let mut apply_plan = None;
     let mut team_repo = None;
+    let mut config = None;
     let mut services = Vec::new();
-    for arg in std::env::args().skip(1) {
-        if next_team_repo {
-            team_repo = Some(arg);
-            next_team_repo = false;
-            continue;
-        }
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .with_context(|| format!("missing value for the {arg} flag"))
+        };
         match arg.as_str() {
             "--live" => dry_run = false,
-            "--team-repo" => next_team_repo = true,
+            "--team-repo" => team_repo = Some(value()?),
+            "--config" => config = Some(PathBuf::from(value()?)),
             "--help" => {
                 usage();
                 return Ok(());
             }
             "--only-print-plan" => only_print_plan = true,
+            "--plan-format" => plan_format = value()?.parse()?,
+            "--save-plan" => save_plan = Some(PathBuf::from(value()?)),
+            "--apply-plan" => apply_plan = Some(PathBuf::from(value()?)),
+            "--keep-going" => keep_going = true,
+            "--allow-destructive" => allow_destructive = true,
             service if AVAILABLE_SERVICES.contains(&service) => services.push(service.to_string()),
             _ => {
                 eprintln!("unknown argument: {arg}");
@@ -64,7 +177,27 @@ fn app() -> anyhow::Result<()> {
         .map(|p| TeamApi::Local(p.into()))
         .unwrap_or(TeamApi::Production);
 
-    if services.is_empty() {
+    let config = config
+        .as_deref()
+        .map(Config::load)
+        .transpose()?
+        .unwrap_or_default();
+
+    if save_plan.is_some() && apply_plan.is_some() {
+        bail!("--save-plan and --apply-plan can't be used together");
+    }
+    let saved_plan = apply_plan.as_deref().map(Plan::load).transpose()?;
+
+    if let Some(saved_plan) = &saved_plan {
+        let planned = saved_plan.services().map(String::from).collect::<Vec<_>>();
+        if let Some(service) = services.iter().find(|s| !planned.contains(s)) {
+            bail!("the saved plan doesn't contain changes for {service}");
+        }
+        if services.is_empty() {
+            info!("no service to synchronize specified, defaulting to the services in the plan");
+            services = planned;
+        }
+    } else if services.is_empty() {
         info!("no service to synchronize specified, defaulting to all services");
         services = AVAILABLE_SERVICES
             .iter()
@@ -77,43 +210,39 @@ fn app() -> anyhow::Result<()> {
         warn!("run the binary with the --live flag to apply the changes.");
     }
 
+    let options = SyncOptions {
+        config,
+        dry_run,
+        allow_destructive,
+        format: plan_format,
+        only_print_plan,
+        save_plan,
+        saved_plan,
+    };
+    let mut plan = Plan::default();
+    let mut failures = Failures::new(keep_going);
+
     for service in services {
         info!("synchronizing {}", service);
-        match service.as_str() {
-            "github" => {
-                let token = get_env("GITHUB_TOKEN")?;
-                let client =
-                    HttpClient::from_url_and_token("https://api.github.com/".to_string(), token)?;
-                let gh_read = Box::new(GitHubApiRead::from_client(client.clone())?);
-                let teams = team_api.get_teams()?;
-                let repos = team_api.get_repos()?;
-                let diff = create_diff(gh_read, teams, repos)?;
-                info!("{}", diff);
-                if !only_print_plan {
-                    let gh_write = GitHubWrite::new(client, dry_run)?;
-                    diff.apply(&gh_write)?;
-                }
-            }
-            "mailgun" => {
-                let token = get_env("MAILGUN_API_TOKEN")?;
-                let encryption_key = get_env("EMAIL_ENCRYPTION_KEY")?;
-                mailgun::run(&token, &encryption_key, &team_api, dry_run)?;
-            }
-            "zulip" => {
-                let username = get_env("ZULIP_USERNAME")?;
-                let token = get_env("ZULIP_API_TOKEN")?;
-                let sync = SyncZulip::new(username, token, &team_api, dry_run)?;
-                let diff = sync.diff_all()?;
-                info!("{}", diff);
-                if !only_print_plan {
-                    diff.apply(&sync)?;
-                }
-            }
+        let synchronize = match service.as_str() {
+            "github" => SyncOptions::synchronize::<GitHubSync>,
+            "mailgun" => SyncOptions::synchronize::<SyncMailgun>,
+            "zulip" => SyncOptions::synchronize::<SyncZulip>,
             _ => panic!("unknown service: {service}"),
-        }
+        };
+        let result = synchronize(&options, &service, &team_api, &mut plan, &mut failures);
+        failures.handle(result, || format!("synchronizing {service}"))?;
+    }
+
+    if plan_format == PlanFormat::Json {
+        println!("{}", plan.to_json()?);
+    }
+    if let Some(path) = &options.save_plan {
+        plan.save(path)?;
+        info!("saved the execution plan to {}", path.display());
     }
 
-    Ok(())
+    failures.into_result()
 }
 
 fn get_env(key: &str) -> anyhow::Result<String> {
@@ -0,0 +1,85 @@
+use anyhow::{bail, Context};
+use serde_json::Value;
+use std::path::Path;
+
+/// The execution plan of a run, holding the serialized diff of every synchronized service.
+///
+/// The same document is printed by `--plan-format json` and stored by `--save-plan`, so that what
+/// a reviewer looked at is exactly what `--apply-plan` will later compare against.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub(crate) struct Plan {
+    services: serde_json::Map<String, Value>,
+}
+
+impl Plan {
+    pub(crate) fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read(path)
+            .with_context(|| format!("failed to read the plan from {}", path.display()))?;
+        serde_json::from_slice(&contents)
+            .with_context(|| format!("failed to parse the plan in {}", path.display()))
+    }
+
+    pub(crate) fn save(&self, path: &Path) -> anyhow::Result<()> {
+        std::fs::write(path, self.to_json()?)
+            .with_context(|| format!("failed to write the plan to {}", path.display()))
+    }
+
+    pub(crate) fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Names of the services included in the plan.
+    pub(crate) fn services(&self) -> impl Iterator<Item = &str> {
+        self.services.keys().map(|s| s.as_str())
+    }
+
+    pub(crate) fn insert<D: serde::Serialize>(
+        &mut self,
+        service: &str,
+        diff: &D,
+    ) -> anyhow::Result<()> {
+        self.services
+            .insert(service.to_string(), serde_json::to_value(diff)?);
+        Ok(())
+    }
+
+    /// Ensure the freshly computed diff of a service matches the one stored in this plan.
+    ///
+    /// A mismatch means either the live state of the service or the team data changed since the
+    /// plan was computed, and applying the fresh diff would not apply what was reviewed.
+    pub(crate) fn ensure_unchanged<D: serde::Serialize>(
+        &self,
+        service: &str,
+        diff: &D,
+    ) -> anyhow::Result<()> {
+        let Some(saved) = self.services.get(service) else {
+            bail!("the saved plan doesn't contain changes for {service}");
+        };
+        if *saved != serde_json::to_value(diff)? {
+            bail!(
+                "the state of {service} drifted since the plan was computed, refusing to apply it \
+                 (generate a new plan with --save-plan)"
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ensure_unchanged() {
+        let mut plan = Plan::default();
+        plan.insert("zulip", &vec!["add foo"]).unwrap();
+        let plan: Plan = serde_json::from_str(&plan.to_json().unwrap()).unwrap();
+
+        assert!(plan.ensure_unchanged("zulip", &vec!["add foo"]).is_ok());
+        assert!(plan
+            .ensure_unchanged("zulip", &vec!["add foo", "remove bar"])
+            .is_err());
+        assert!(plan.ensure_unchanged("github", &vec!["add foo"]).is_err());
+    }
+}
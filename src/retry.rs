@@ -0,0 +1,156 @@
+use anyhow::Context;
+use log::warn;
+use reqwest::blocking::{RequestBuilder, Response};
+use reqwest::StatusCode;
+use std::time::Duration;
+
+/// How requests failing because of transient errors (connection errors, timeouts and server
+/// errors) are retried by the clients of all the services.
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct RetryPolicy {
+    /// Maximum number of times each request is sent, including the first attempt.
+    pub(crate) attempts: u32,
+    /// Delay before the first retry in milliseconds, doubled after every retry.
+    pub(crate) initial_backoff_ms: u64,
+    /// Upper bound of the delay between two attempts, in milliseconds.
+    pub(crate) max_backoff_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 4,
+            initial_backoff_ms: 1_000,
+            max_backoff_ms: 30_000,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Send a request, retrying it on transient failures if it's `repeatable`, meaning that
+    /// sending it more than once has the same effect as sending it once. Once the attempts are
+    /// exhausted, the outcome of the last one is returned.
+    pub(crate) fn send(
+        &self,
+        request: &RequestBuilder,
+        repeatable: bool,
+    ) -> anyhow::Result<Response> {
+        let mut attempt = 1;
+        loop {
+            let result = request
+                .try_clone()
+                .context("failed to clone the request")?
+                .send();
+            let failure = match &result {
+                Ok(resp) if is_transient_status(resp.status()) => {
+                    format!("{} returned {}", resp.url(), resp.status())
+                }
+                Err(err) if err.is_connect() || err.is_timeout() || err.is_request() => {
+                    err.to_string()
+                }
+                _ => return Ok(result?),
+            };
+            if !repeatable || attempt >= self.attempts {
+                return Ok(result?);
+            }
+
+            let delay = self.backoff(attempt);
+            warn!(
+                "transient failure ({failure}), retrying in {}ms (attempt {attempt} of {})",
+                delay.as_millis(),
+                self.attempts
+            );
+            std::thread::sleep(delay);
+            attempt += 1;
+        }
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u64.saturating_pow(attempt - 1);
+        let delay = self.initial_backoff_ms.saturating_mul(factor);
+        Duration::from_millis(delay.min(self.max_backoff_ms))
+    }
+}
+
+fn is_transient_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::{MockResponse, MockServer};
+    use reqwest::blocking::Client;
+    use reqwest::Method;
+
+    const POLICY: RetryPolicy = RetryPolicy {
+        attempts: 3,
+        initial_backoff_ms: 0,
+        max_backoff_ms: 0,
+    };
+
+    #[test]
+    fn retry_transient_failures() {
+        let server = MockServer::start(vec![
+            MockResponse::new(502, "bad gateway"),
+            MockResponse::new(503, "unavailable"),
+            MockResponse::new(200, "ok"),
+        ]);
+        let request = Client::new().request(Method::GET, server.url());
+        let resp = POLICY.send(&request, true).unwrap();
+        assert_eq!(resp.text().unwrap(), "ok");
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[test]
+    fn give_up_after_the_last_attempt() {
+        let server = MockServer::start(vec![
+            MockResponse::new(503, "unavailable"),
+            MockResponse::new(503, "unavailable"),
+            MockResponse::new(503, "still unavailable"),
+        ]);
+        let request = Client::new().request(Method::GET, server.url());
+        let resp = POLICY.send(&request, true).unwrap();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(resp.text().unwrap(), "still unavailable");
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[test]
+    fn do_not_retry_unrepeatable_requests() {
+        let server = MockServer::start(vec![MockResponse::new(503, "unavailable")]);
+        let request = Client::new().request(Method::POST, server.url());
+        let resp = POLICY.send(&request, false).unwrap();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn do_not_retry_client_errors() {
+        let server = MockServer::start(vec![MockResponse::new(404, "not found")]);
+        let request = Client::new().request(Method::GET, server.url());
+        let resp = POLICY.send(&request, true).unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn exponential_backoff() {
+        let policy = RetryPolicy {
+            attempts: 10,
+            initial_backoff_ms: 100,
+            max_backoff_ms: 1_000,
+        };
+        let delays = (1..6)
+            .map(|a| policy.backoff(a).as_millis())
+            .collect::<Vec<_>>();
+        assert_eq!(delays, [100, 200, 400, 800, 1_000]);
+    }
+}
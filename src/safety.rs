@@ -0,0 +1,180 @@
+//! Limits protecting the services from plans removing much more than usual, for example because
+//! of a bad commit in the team repo or of a broken Team API response. Plans exceeding the limits
+//! are refused unless `--allow-destructive` is passed.
+
+/// Maximum amount of destructive changes a plan can contain, for each service.
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct SafetyLimits {
+    /// Maximum number of teams deleted.
+    pub(crate) max_team_deletions: usize,
+    /// Maximum number of members removed from orgs, teams, user groups or mailing lists.
+    pub(crate) max_member_removals: usize,
+    /// Maximum number of Mailgun routes deleted.
+    pub(crate) max_route_deletions: usize,
+    /// Maximum number of permissions of teams and users removed from repos.
+    pub(crate) max_permission_removals: usize,
+    /// Maximum number of branch protections and rulesets deleted and apps uninstalled from repos.
+    pub(crate) max_repo_setting_deletions: usize,
+    /// Maximum percentage of the members of a single group removed.
+    pub(crate) max_removed_percentage: usize,
+    /// Groups with less members than this are not subject to `max_removed_percentage`, as
+    /// removing a single member would already exceed it.
+    pub(crate) min_group_size: usize,
+}
+
+impl Default for SafetyLimits {
+    fn default() -> Self {
+        Self {
+            max_team_deletions: 5,
+            max_member_removals: 50,
+            max_route_deletions: 10,
+            max_permission_removals: 20,
+            max_repo_setting_deletions: 10,
+            max_removed_percentage: 50,
+            min_group_size: 5,
+        }
+    }
+}
+
+/// The destructive changes contained in the plan of a service.
+#[derive(Default, Debug)]
+pub(crate) struct Removals {
+    /// Names of the deleted teams.
+    pub(crate) team_deletions: Vec<String>,
+    /// Names of the deleted routes.
+    pub(crate) route_deletions: Vec<String>,
+    /// Members removed from GitHub orgs, as "member from org".
+    pub(crate) org_member_removals: Vec<String>,
+    /// Permissions removed from repos, as "collaborator from org/repo".
+    pub(crate) permission_removals: Vec<String>,
+    /// Branch protections and rulesets deleted and apps uninstalled from repos.
+    pub(crate) repo_setting_deletions: Vec<String>,
+    /// Members removed from the groups (teams, user groups or mailing lists) that are kept.
+    pub(crate) groups: Vec<GroupRemovals>,
+}
+
+#[derive(Debug)]
+pub(crate) struct GroupRemovals {
+    pub(crate) name: String,
+    /// Number of members before the changes are applied.
+    pub(crate) size: usize,
+    pub(crate) removed: usize,
+}
+
+impl Removals {
+    /// Record that `removed` of the `size` members of a group are removed.
+    pub(crate) fn group(&mut self, name: impl Into<String>, size: usize, removed: usize) {
+        if removed > 0 {
+            self.groups.push(GroupRemovals {
+                name: name.into(),
+                size,
+                removed,
+            });
+        }
+    }
+}
+
+impl SafetyLimits {
+    /// Describe every limit exceeded by `removals`.
+    pub(crate) fn violations(&self, removals: &Removals) -> Vec<String> {
+        let mut violations = Vec::new();
+        if removals.team_deletions.len() > self.max_team_deletions {
+            violations.push(format!(
+                "{} teams would be deleted (limit: {}): {}",
+                removals.team_deletions.len(),
+                self.max_team_deletions,
+                removals.team_deletions.join(", ")
+            ));
+        }
+        if removals.route_deletions.len() > self.max_route_deletions {
+            violations.push(format!(
+                "{} routes would be deleted (limit: {}): {}",
+                removals.route_deletions.len(),
+                self.max_route_deletions,
+                removals.route_deletions.join(", ")
+            ));
+        }
+        if removals.permission_removals.len() > self.max_permission_removals {
+            violations.push(format!(
+                "{} repo permissions would be removed (limit: {}): {}",
+                removals.permission_removals.len(),
+                self.max_permission_removals,
+                removals.permission_removals.join(", ")
+            ));
+        }
+        if removals.repo_setting_deletions.len() > self.max_repo_setting_deletions {
+            violations.push(format!(
+                "{} repo settings would be deleted (limit: {}): {}",
+                removals.repo_setting_deletions.len(),
+                self.max_repo_setting_deletions,
+                removals.repo_setting_deletions.join(", ")
+            ));
+        }
+        let member_removals = removals.org_member_removals.len()
+            + removals.groups.iter().map(|g| g.removed).sum::<usize>();
+        if member_removals > self.max_member_removals {
+            violations.push(format!(
+                "{member_removals} members would be removed (limit: {})",
+                self.max_member_removals
+            ));
+        }
+        for group in &removals.groups {
+            if group.size < self.min_group_size {
+                continue;
+            }
+            let percentage = group.removed * 100 / group.size;
+            if percentage > self.max_removed_percentage {
+                violations.push(format!(
+                    "{} of the {} members of {} would be removed ({percentage}%, limit: {}%)",
+                    group.removed, group.size, group.name, self.max_removed_percentage
+                ));
+            }
+        }
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn within_limits() {
+        let mut removals = Removals {
+            team_deletions: vec!["rust-lang/wg-old".into()],
+            ..Removals::default()
+        };
+        removals.group("rust-lang/infra", 10, 5);
+        // Small groups are only subject to the global limits.
+        removals.group("rust-lang/tiny", 2, 2);
+        assert!(SafetyLimits::default().violations(&removals).is_empty());
+    }
+
+    #[test]
+    fn report_all_exceeded_limits() {
+        let limits = SafetyLimits {
+            max_team_deletions: 1,
+            max_member_removals: 5,
+            max_permission_removals: 1,
+            ..SafetyLimits::default()
+        };
+        let mut removals = Removals {
+            team_deletions: vec!["rust-lang/a".into(), "rust-lang/b".into()],
+            permission_removals: vec![
+                "team 'a' from rust-lang/rust".into(),
+                "user 'b' from rust-lang/rust".into(),
+            ],
+            ..Removals::default()
+        };
+        removals.group("rust-lang/infra", 8, 6);
+        removals.group("rust-lang/compiler", 40, 1);
+        removals.group("rust-lang/lang", 5, 0);
+        insta::assert_snapshot!(limits.violations(&removals).join("\n"), @r###"
+        2 teams would be deleted (limit: 1): rust-lang/a, rust-lang/b
+        2 repo permissions would be removed (limit: 1): team 'a' from rust-lang/rust, user 'b' from rust-lang/rust
+        7 members would be removed (limit: 5)
+        6 of the 8 members of rust-lang/infra would be removed (75%, limit: 50%)
+        "###);
+    }
+}
@@ -0,0 +1,36 @@
+use crate::config::Config;
+use crate::failures::Failures;
+use crate::safety::Removals;
+use crate::team_api::TeamApi;
+use std::fmt::Display;
+
+/// A service synchronized with the contents of the team repo.
+///
+/// Every service goes through the same phases: the desired state is loaded from the Team API
+/// when the service is created, `diff` reads the current state of the service and computes the
+/// changes needed to reach the desired state, and `apply` performs those changes. Keeping the
+/// phases separate allows the changes to be printed, saved and reviewed before applying them.
+pub(crate) trait SyncService: Sized {
+    /// The changes needed to synchronize the service.
+    type Diff: Display + serde::Serialize;
+
+    /// Load the desired state of the service, and prepare the clients needed to talk to it.
+    fn load(team_api: &TeamApi, config: &Config, dry_run: bool) -> anyhow::Result<Self>;
+
+    /// Read the current state of the service and compute the changes to apply.
+    fn diff(&self) -> anyhow::Result<Self::Diff>;
+
+    /// Collect the destructive changes contained in a diff, to check them against the safety
+    /// limits before applying it.
+    fn removals(&self, diff: &Self::Diff) -> Removals;
+
+    /// Check the policies a diff must satisfy. Their violations fail the run even when the diff
+    /// is only printed or saved, but don't prevent it from being applied.
+    fn check(&self, _diff: &Self::Diff) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Apply the changes computed by `diff`, handling the failures of independent changes
+    /// through `failures`.
+    fn apply(&self, diff: Self::Diff, failures: &mut Failures) -> anyhow::Result<()>;
+}
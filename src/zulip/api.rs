@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 
+use crate::retry::RetryPolicy;
 use reqwest::blocking::Client;
 use serde::Deserialize;
 
@@ -11,16 +12,18 @@ pub(crate) struct ZulipApi {
     client: Client,
     username: String,
     token: String,
+    retry: RetryPolicy,
     dry_run: bool,
 }
 
 impl ZulipApi {
     /// Create a new `ZulipApi` instance
-    pub(crate) fn new(username: String, token: String, dry_run: bool) -> Self {
+    pub(crate) fn new(username: String, token: String, retry: RetryPolicy, dry_run: bool) -> Self {
         Self {
             client: Client::new(),
             username,
             token,
+            retry,
             dry_run,
         }
     }
@@ -144,13 +147,15 @@ impl ZulipApi {
         Ok(())
     }
 
-    /// Perform a request against the Zulip API
+    /// Perform a request against the Zulip API, retrying it on transient failures if it's
+    /// idempotent
     fn req(
         &self,
         method: reqwest::Method,
         path: &str,
         form: Option<HashMap<&str, &str>>,
     ) -> anyhow::Result<reqwest::blocking::Response> {
+        let idempotent = method.is_idempotent();
         let mut req = self
             .client
             .request(method, format!("{ZULIP_BASE_URL}{path}"))
@@ -159,7 +164,7 @@ impl ZulipApi {
             req = req.form(&form);
         }
 
-        Ok(req.send()?)
+        self.retry.send(&req, idempotent)
     }
 }
 
@@ -1,5 +1,9 @@
 mod api;
 
+use crate::config::Config;
+use crate::failures::Failures;
+use crate::safety::Removals;
+use crate::service::SyncService;
 use crate::team_api::TeamApi;
 use api::{ZulipApi, ZulipUserGroup};
 use rust_team_data::v1::ZulipGroupMember;
@@ -11,14 +15,51 @@ pub(crate) struct SyncZulip {
     user_group_definitions: BTreeMap<String, Vec<u64>>,
 }
 
+impl SyncService for SyncZulip {
+    type Diff = Diff;
+
+    fn load(team_api: &TeamApi, config: &Config, dry_run: bool) -> anyhow::Result<Self> {
+        let username = crate::get_env("ZULIP_USERNAME")?;
+        let token = crate::get_env("ZULIP_API_TOKEN")?;
+        SyncZulip::new(username, token, team_api, config, dry_run)
+    }
+
+    fn diff(&self) -> anyhow::Result<Diff> {
+        self.diff_all()
+    }
+
+    fn removals(&self, diff: &Diff) -> Removals {
+        let mut removals = Removals::default();
+        for user_group_diff in &diff.user_group_diffs {
+            if let UserGroupDiff::Update(u) = user_group_diff {
+                let size = self
+                    .zulip_controller
+                    .user_group_members_from_name(&u.name)
+                    .map_or(0, |members| members.len());
+                removals.group(
+                    format!("user group {}", u.name),
+                    size,
+                    u.member_id_deletions.len(),
+                );
+            }
+        }
+        removals
+    }
+
+    fn apply(&self, diff: Diff, failures: &mut Failures) -> anyhow::Result<()> {
+        diff.apply(self, failures)
+    }
+}
+
 impl SyncZulip {
     pub(crate) fn new(
         username: String,
         token: String,
         team_api: &TeamApi,
+        config: &Config,
         dry_run: bool,
     ) -> anyhow::Result<Self> {
-        let zulip_api = ZulipApi::new(username, token, dry_run);
+        let zulip_api = ZulipApi::new(username, token, config.retry, dry_run);
         let user_group_definitions = get_user_group_definitions(team_api, &zulip_api)?;
         let zulip_controller = ZulipController::new(zulip_api)?;
         Ok(Self {
@@ -94,14 +135,15 @@ impl SyncZulip {
     }
 }
 
+#[derive(serde::Serialize)]
 pub(crate) struct Diff {
     user_group_diffs: Vec<UserGroupDiff>,
 }
 
 impl Diff {
-    pub(crate) fn apply(&self, sync: &SyncZulip) -> anyhow::Result<()> {
+    pub(crate) fn apply(&self, sync: &SyncZulip, failures: &mut Failures) -> anyhow::Result<()> {
         for user_group_diff in &self.user_group_diffs {
-            user_group_diff.apply(sync)?;
+            failures.handle(user_group_diff.apply(sync), || user_group_diff.context())?;
         }
         Ok(())
     }
@@ -117,6 +159,8 @@ impl std::fmt::Display for Diff {
     }
 }
 
+#[derive(serde::Serialize)]
+#[serde(rename_all = "snake_case")]
 enum UserGroupDiff {
     Create(CreateUserGroupDiff),
     Update(UpdateUserGroupDiff),
@@ -129,6 +173,13 @@ impl UserGroupDiff {
             UserGroupDiff::Update(u) => u.apply(sync),
         }
     }
+
+    fn context(&self) -> String {
+        match self {
+            UserGroupDiff::Create(c) => format!("creating user group {}", c.name),
+            UserGroupDiff::Update(u) => format!("updating user group {}", u.name),
+        }
+    }
 }
 
 impl std::fmt::Display for UserGroupDiff {
@@ -140,6 +191,7 @@ impl std::fmt::Display for UserGroupDiff {
     }
 }
 
+#[derive(serde::Serialize)]
 struct CreateUserGroupDiff {
     name: String,
     description: String,
@@ -166,6 +218,7 @@ impl std::fmt::Display for CreateUserGroupDiff {
     }
 }
 
+#[derive(serde::Serialize)]
 struct UpdateUserGroupDiff {
     name: String,
     user_group_id: u64,